@@ -0,0 +1,49 @@
+// Boots the 48K ROM for a fixed number of frames, with the ULA's frame
+// interrupt but no display. Covers the RAM test, the system variable setup
+// and the first pass of the editor's keyboard loop.
+//
+//     cargo bench --bench rom_boot
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use zx_spectrum_emulator::cpu::Cpu;
+use zx_spectrum_emulator::emulator::SplitBus;
+use zx_spectrum_emulator::io::IoController;
+use zx_spectrum_emulator::memory::{Memory, load_rom};
+
+const ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/48.rom");
+const FRAMES: u32 = 100;
+
+const CYCLES_PER_FRAME: u32 = 69888;
+const INT_LENGTH: u32 = 32;
+
+fn boot(rom: &[u8], frames: u32) -> Cpu {
+    let mut cpu = Cpu::default();
+    let mut memory = Memory::new(rom.to_vec());
+    let mut io = IoController::new();
+
+    for _ in 0..frames {
+        let mut frame_cycles = 0;
+        while frame_cycles < CYCLES_PER_FRAME {
+            cpu.int_line = frame_cycles < INT_LENGTH;
+            frame_cycles += cpu.step(&mut SplitBus::new(&mut memory, &mut io));
+        }
+    }
+
+    cpu
+}
+
+fn rom_boot(c: &mut Criterion) {
+    let rom = load_rom(ROM_PATH).expect("48.rom");
+
+    let mut group = c.benchmark_group("rom_boot");
+    group.sample_size(20);
+    group.bench_function(format!("{} frames", FRAMES), |b| {
+        b.iter(|| boot(black_box(&rom), FRAMES))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, rom_boot);
+criterion_main!(benches);
@@ -0,0 +1,215 @@
+use super::Cpu;
+use super::flags::{
+    FLAG_C, FLAG_H, FLAG_N, FLAG_PV, FLAG_S, FLAG_X, FLAG_Y, FLAG_Z, HALF_CARRY_ADD,
+    HALF_CARRY_SUB, OVERFLOW_ADD, OVERFLOW_SUB, SZ53, SZ53P, half_carry_index,
+};
+
+// Arithmetic and logic shared by every opcode table. Operands are already
+// fetched, so none of this touches the bus.
+impl Cpu {
+    // The accumulator operations, selected by bits 3-5 of the opcode:
+    // ADD, ADC, SUB, SBC, AND, XOR, OR, CP
+    #[inline]
+    pub(super) fn alu(&mut self, op: u8, val: u8) {
+        match op {
+            0 => self.a = self.add8(val, 0),
+            1 => self.a = self.add8(val, self.f & FLAG_C),
+            2 => self.a = self.sub8(val, 0),
+            3 => self.a = self.sub8(val, self.f & FLAG_C),
+            4 => {
+                self.a &= val;
+                self.set_flags(SZ53P[self.a as usize] | FLAG_H);
+            }
+            5 => {
+                self.a ^= val;
+                self.set_flags(SZ53P[self.a as usize]);
+            }
+            6 => {
+                self.a |= val;
+                self.set_flags(SZ53P[self.a as usize]);
+            }
+            7 => {
+                self.sub8(val, 0);
+                // Flags 3 and 5 come from the operand, not the result
+                self.f = (self.f & !(FLAG_X | FLAG_Y)) | (val & (FLAG_X | FLAG_Y));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn add8(&mut self, val: u8, carry: u8) -> u8 {
+        let full = self.a as u16 + val as u16 + carry as u16;
+        let result = full as u8;
+        let index = half_carry_index(self.a, val, result);
+
+        self.set_flags(
+            ((full >> 8) as u8 & FLAG_C)
+                | HALF_CARRY_ADD[index & 0x07]
+                | OVERFLOW_ADD[index >> 4]
+                | SZ53[result as usize],
+        );
+        result
+    }
+
+    // A minus val minus carry, setting the flags but leaving A alone
+    #[inline]
+    fn sub8(&mut self, val: u8, carry: u8) -> u8 {
+        let full = (self.a as u16)
+            .wrapping_sub(val as u16)
+            .wrapping_sub(carry as u16);
+        let result = full as u8;
+        let index = half_carry_index(self.a, val, result);
+
+        self.set_flags(
+            ((full >> 8) as u8 & FLAG_C)
+                | FLAG_N
+                | HALF_CARRY_SUB[index & 0x07]
+                | OVERFLOW_SUB[index >> 4]
+                | SZ53[result as usize],
+        );
+        result
+    }
+
+    // INC and DEC leave the carry alone
+    #[inline]
+    pub(super) fn inc8(&mut self, val: u8) -> u8 {
+        let result = val.wrapping_add(1);
+        let mut flags = (self.f & FLAG_C) | SZ53[result as usize];
+        if val == 0x7F {
+            flags |= FLAG_PV;
+        }
+        if val & 0x0F == 0x0F {
+            flags |= FLAG_H;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    #[inline]
+    pub(super) fn dec8(&mut self, val: u8) -> u8 {
+        let result = val.wrapping_sub(1);
+        let mut flags = (self.f & FLAG_C) | FLAG_N | SZ53[result as usize];
+        if val == 0x80 {
+            flags |= FLAG_PV;
+        }
+        if val & 0x0F == 0x00 {
+            flags |= FLAG_H;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    // The CB rotates and shifts, selected by bits 3-5 of the opcode:
+    // RLC, RRC, RL, RR, SLA, SRA, SLL (undocumented), SRL
+    #[inline]
+    pub(super) fn rotate_shift(&mut self, op: u8, val: u8) -> u8 {
+        let carry_in = self.f & FLAG_C;
+        let (result, carry) = match op {
+            0 => (val.rotate_left(1), val >> 7),
+            1 => (val.rotate_right(1), val & 1),
+            2 => ((val << 1) | carry_in, val >> 7),
+            3 => ((val >> 1) | (carry_in << 7), val & 1),
+            4 => (val << 1, val >> 7),
+            5 => ((val >> 1) | (val & 0x80), val & 1),
+            6 => ((val << 1) | 1, val >> 7),
+            7 => (val >> 1, val & 1),
+            _ => unreachable!(),
+        };
+        self.set_flags(SZ53P[result as usize] | carry);
+        result
+    }
+
+    // BIT n. Flags 3 and 5 come from xy, which depends on the addressing
+    // mode: the operand for registers, MEMPTR for (HL) and the high byte of
+    // the address for (IX+d)
+    #[inline]
+    pub(super) fn bit(&mut self, bit: u8, val: u8, xy: u8) {
+        let result = val & (1 << bit);
+        let mut flags = (self.f & FLAG_C) | FLAG_H | (xy & (FLAG_X | FLAG_Y));
+        if result == 0 {
+            flags |= FLAG_Z | FLAG_PV;
+        }
+        if result & 0x80 != 0 {
+            flags |= FLAG_S;
+        }
+        self.set_flags(flags);
+    }
+
+    // The accumulator rotates only touch C, H, N, 3 and 5
+    #[inline]
+    pub(super) fn rotate_a(&mut self, op: u8) {
+        let kept = self.f & (FLAG_S | FLAG_Z | FLAG_PV);
+        self.a = self.rotate_shift(op, self.a);
+        self.set_flags(kept | (self.f & FLAG_C) | (self.a & (FLAG_X | FLAG_Y)));
+    }
+
+    // ADD HL/IX/IY,rr leaves S, Z and P/V alone. H is the carry out of bit 11.
+    #[inline]
+    pub(super) fn add16(&mut self, a: u16, b: u16) -> u16 {
+        let full = a as u32 + b as u32;
+        let result = full as u16;
+        let index = half_carry_index((a >> 8) as u8, (b >> 8) as u8, (result >> 8) as u8);
+        self.wz = a.wrapping_add(1);
+
+        self.set_flags(
+            (self.f & (FLAG_S | FLAG_Z | FLAG_PV))
+                | ((full >> 16) as u8 & FLAG_C)
+                | HALF_CARRY_ADD[index & 0x07]
+                | ((result >> 8) as u8 & (FLAG_X | FLAG_Y)),
+        );
+        result
+    }
+
+    // ADC HL,rr and SBC HL,rr set every flag from the 16-bit result
+    #[inline]
+    pub(super) fn adc16(&mut self, val: u16) {
+        let hl = self.hl();
+        let full = hl as u32 + val as u32 + (self.f & FLAG_C) as u32;
+        let result = full as u16;
+        let index = half_carry_index((hl >> 8) as u8, (val >> 8) as u8, (result >> 8) as u8);
+        self.set_hl(result);
+        self.wz = hl.wrapping_add(1);
+
+        self.set_flags(self.flags16(
+            full,
+            result,
+            HALF_CARRY_ADD[index & 0x07],
+            OVERFLOW_ADD[index >> 4],
+        ));
+    }
+
+    #[inline]
+    pub(super) fn sbc16(&mut self, val: u16) {
+        let hl = self.hl();
+        let full = (hl as u32)
+            .wrapping_sub(val as u32)
+            .wrapping_sub((self.f & FLAG_C) as u32);
+        let result = full as u16;
+        let index = half_carry_index((hl >> 8) as u8, (val >> 8) as u8, (result >> 8) as u8);
+        self.set_hl(result);
+        self.wz = hl.wrapping_add(1);
+
+        self.set_flags(
+            FLAG_N
+                | self.flags16(
+                    full,
+                    result,
+                    HALF_CARRY_SUB[index & 0x07],
+                    OVERFLOW_SUB[index >> 4],
+                ),
+        );
+    }
+
+    #[inline]
+    fn flags16(&self, full: u32, result: u16, half_carry: u8, overflow: u8) -> u8 {
+        let mut flags = ((full >> 16) as u8 & FLAG_C)
+            | half_carry
+            | overflow
+            | (SZ53[(result >> 8) as usize] & !FLAG_Z);
+        if result == 0 {
+            flags |= FLAG_Z;
+        }
+        flags
+    }
+}
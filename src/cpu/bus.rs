@@ -0,0 +1,49 @@
+// The kinds of machine cycle the CPU runs, as reported to Bus::start_cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cycle {
+    OpcodeFetch,
+    MemoryRead,
+    MemoryWrite,
+    PortRead,
+    PortWrite,
+    InterruptAck,
+    // One T-state of internal work, with the last address left on the bus
+    Internal,
+}
+
+// Everything the CPU sees of the machine around it: memory, opcode fetches
+// (M1 cycles), the port space and the passage of time. Implement this to run
+// the core against a different memory map, a flat test RAM or an embedded
+// host.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+
+    // M1 opcode fetch. Separate from read() so machines can tell them apart,
+    // e.g. for contention or to trap execution at an address.
+    fn fetch_opcode(&mut self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    fn port_in(&mut self, port: u16) -> u8;
+    fn port_out(&mut self, port: u16, val: u8);
+
+    // The byte on the data bus during an interrupt acknowledge cycle: the
+    // low byte of the vector address in IM 2, or the instruction to execute
+    // in IM 0. With nothing driving the bus it floats high.
+    fn interrupt_data(&mut self) -> u8 {
+        0xFF
+    }
+
+    // Called as each machine cycle starts, with the address the CPU puts on
+    // the bus (the port for I/O cycles). Return any wait states to insert,
+    // e.g. for ULA contention. Internal cycles are reported per T-state.
+    fn start_cycle(&mut self, _cycle: Cycle, _addr: u16) -> u32 {
+        0
+    }
+
+    // Told about T-states as the CPU uses them. Memory accesses happen at
+    // the end of their cycle and port accesses on its third T-state, so a
+    // bus that counts ticks knows exactly when each one falls.
+    fn tick(&mut self, _tstates: u32) {}
+}
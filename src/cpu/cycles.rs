@@ -0,0 +1,79 @@
+use super::{Bus, Cpu, Cycle};
+
+// Machine cycles. Every bus access and internal delay goes through here, so
+// an instruction's T-state count is the sum of the cycles it runs.
+impl Cpu {
+    fn run_cycle<B: Bus>(&mut self, bus: &mut B, cycle: Cycle, addr: u16, tstates: u32) {
+        let tstates = tstates + bus.start_cycle(cycle, addr);
+        self.advance(bus, tstates);
+    }
+
+    fn advance<B: Bus>(&mut self, bus: &mut B, tstates: u32) {
+        self.tstates += tstates;
+        bus.tick(tstates);
+    }
+
+    // M1: opcode fetch plus refresh (4 T-states). Every M1 increments R,
+    // so a prefixed opcode counts twice.
+    pub(super) fn opcode_cycle<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u8 {
+        self.run_cycle(bus, Cycle::OpcodeFetch, addr, 4);
+        self.increment_r(1);
+        bus.fetch_opcode(addr)
+    }
+
+    pub(super) fn read_byte<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u8 {
+        self.run_cycle(bus, Cycle::MemoryRead, addr, 3);
+        bus.read(addr)
+    }
+
+    pub(super) fn write_byte<B: Bus>(&mut self, bus: &mut B, addr: u16, val: u8) {
+        self.run_cycle(bus, Cycle::MemoryWrite, addr, 3);
+        bus.write(addr, val);
+    }
+
+    pub(super) fn read_word<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u16 {
+        let lo = self.read_byte(bus, addr) as u16;
+        let hi = self.read_byte(bus, addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    pub(super) fn write_word<B: Bus>(&mut self, bus: &mut B, addr: u16, val: u16) {
+        self.write_byte(bus, addr, val as u8);
+        self.write_byte(bus, addr.wrapping_add(1), (val >> 8) as u8);
+    }
+
+    // I/O cycles are 4 T-states including the automatic wait state. The
+    // port is read or written on the third.
+    pub(super) fn port_read<B: Bus>(&mut self, bus: &mut B, port: u16) -> u8 {
+        self.run_cycle(bus, Cycle::PortRead, port, 3);
+        let val = bus.port_in(port);
+        self.advance(bus, 1);
+        val
+    }
+
+    pub(super) fn port_write<B: Bus>(&mut self, bus: &mut B, port: u16, val: u8) {
+        self.run_cycle(bus, Cycle::PortWrite, port, 3);
+        bus.port_out(port, val);
+        self.advance(bus, 1);
+    }
+
+    // Interrupt acknowledge: an M1 cycle stretched by two wait states
+    pub(super) fn interrupt_ack_cycle<B: Bus>(&mut self, bus: &mut B) {
+        let pc = self.pc;
+        self.run_cycle(bus, Cycle::InterruptAck, pc, 6);
+        self.increment_r(1);
+    }
+
+    // T-states spent on internal work. The address bus still holds addr,
+    // which matters to machines that contend on it.
+    pub(super) fn internal<B: Bus>(&mut self, bus: &mut B, addr: u16, tstates: u32) {
+        for _ in 0..tstates {
+            self.run_cycle(bus, Cycle::Internal, addr, 1);
+        }
+    }
+
+    // The refresh address left on the bus after an M1 cycle
+    pub(super) fn ir(&self) -> u16 {
+        ((self.i as u16) << 8) | self.r as u16
+    }
+}
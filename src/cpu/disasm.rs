@@ -0,0 +1,297 @@
+// Z80 disassembler covering every documented and undocumented opcode,
+// decoded by the same x/y/z fields as the CPU itself. Numbers are printed
+// in hex with a $ prefix, and relative jumps show their target address.
+
+const REGS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const PAIRS_AF: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU_OPS: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROTATIONS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+const ACCUMULATOR_OPS: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+const BLOCK_OPS: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+const INTERRUPT_MODES: [&str; 4] = ["0", "0", "1", "2"];
+
+pub struct Instruction {
+    pub text: String,
+    // Bytes taken up, prefixes and operands included
+    pub length: u16,
+}
+
+// Disassemble the instruction at addr, reading bytes through read, e.g.
+// disassemble(cpu.pc, |addr| memory.read(addr))
+pub fn disassemble<F: FnMut(u16) -> u8>(addr: u16, read: F) -> Instruction {
+    let mut decoder = Decoder {
+        read,
+        addr,
+        length: 0,
+        index: None,
+    };
+    let text = decoder.instruction();
+    Instruction {
+        text,
+        length: decoder.length,
+    }
+}
+
+struct Decoder<F> {
+    read: F,
+    addr: u16,
+    length: u16,
+    // "IX" or "IY" after a DD or FD prefix
+    index: Option<&'static str>,
+}
+
+impl<F: FnMut(u16) -> u8> Decoder<F> {
+    fn next(&mut self) -> u8 {
+        let byte = (self.read)(self.addr.wrapping_add(self.length));
+        self.length += 1;
+        byte
+    }
+
+    fn byte(&mut self) -> String {
+        format!("${:02X}", self.next())
+    }
+
+    fn word(&mut self) -> String {
+        let lo = self.next() as u16;
+        let hi = self.next() as u16;
+        format!("${:04X}", (hi << 8) | lo)
+    }
+
+    // JR and DJNZ, shown as the address they jump to
+    fn relative(&mut self) -> String {
+        let offset = self.next() as i8;
+        let target = self
+            .addr
+            .wrapping_add(self.length)
+            .wrapping_add(offset as u16);
+        format!("${:04X}", target)
+    }
+
+    // (IX+d), reading the displacement
+    fn indexed(&mut self, index: &str) -> String {
+        let d = self.next() as i8;
+        if d < 0 {
+            format!("({}-${:02X})", index, d.unsigned_abs())
+        } else {
+            format!("({}+${:02X})", index, d)
+        }
+    }
+
+    // An 8-bit register operand. With an index prefix (HL) becomes (IX+d)
+    // and H and L become IXH and IXL, unless the instruction also uses
+    // (IX+d), when they stay as H and L.
+    fn reg(&mut self, r: u8, memory_operand: bool) -> String {
+        match (self.index, r) {
+            (Some(index), 6) => self.indexed(index),
+            (Some(index), 4 | 5) if !memory_operand => {
+                format!("{}{}", index, if r == 4 { "H" } else { "L" })
+            }
+            _ => REGS[r as usize].to_string(),
+        }
+    }
+
+    fn pair(&self, p: u8) -> &'static str {
+        match self.index {
+            Some(index) if p == 2 => index,
+            _ => PAIRS[p as usize],
+        }
+    }
+
+    fn pair_af(&self, p: u8) -> &'static str {
+        match self.index {
+            Some(index) if p == 2 => index,
+            _ => PAIRS_AF[p as usize],
+        }
+    }
+
+    fn instruction(&mut self) -> String {
+        let opcode = self.next();
+        match opcode {
+            0xCB => match self.index {
+                Some(index) => self.index_cb_instruction(index),
+                None => {
+                    let opcode = self.next();
+                    self.cb_instruction(opcode)
+                }
+            },
+            // An index prefix followed by DD or FD acts as a NOP on its own,
+            // and the next prefix starts a new instruction
+            0xDD | 0xFD if self.index.is_some() => {
+                self.length -= 1;
+                "NOP".to_string()
+            }
+            // Before ED the index prefix is ignored, but the CPU runs both as
+            // one instruction, so the prefix still counts towards the length
+            0xED => {
+                self.index = None;
+                let opcode = self.next();
+                self.ed_instruction(opcode)
+            }
+            0xDD => {
+                self.index = Some("IX");
+                self.instruction()
+            }
+            0xFD => {
+                self.index = Some("IY");
+                self.instruction()
+            }
+            _ => self.main_instruction(opcode),
+        }
+    }
+
+    fn main_instruction(&mut self, opcode: u8) -> String {
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let p = y >> 1;
+        let q = y & 1;
+
+        match opcode >> 6 {
+            0 => match z {
+                0 => match y {
+                    0 => "NOP".to_string(),
+                    1 => "EX AF,AF'".to_string(),
+                    2 => format!("DJNZ {}", self.relative()),
+                    3 => format!("JR {}", self.relative()),
+                    _ => format!("JR {},{}", CONDITIONS[(y - 4) as usize], self.relative()),
+                },
+                1 if q == 0 => format!("LD {},{}", self.pair(p), self.word()),
+                1 => format!("ADD {},{}", self.pair(2), self.pair(p)),
+                2 => {
+                    let pair = self.pair(2);
+                    match y {
+                        0 => "LD (BC),A".to_string(),
+                        1 => "LD A,(BC)".to_string(),
+                        2 => "LD (DE),A".to_string(),
+                        3 => "LD A,(DE)".to_string(),
+                        4 => format!("LD ({}),{}", self.word(), pair),
+                        5 => format!("LD {},({})", pair, self.word()),
+                        6 => format!("LD ({}),A", self.word()),
+                        _ => format!("LD A,({})", self.word()),
+                    }
+                }
+                3 if q == 0 => format!("INC {}", self.pair(p)),
+                3 => format!("DEC {}", self.pair(p)),
+                4 => format!("INC {}", self.reg(y, false)),
+                5 => format!("DEC {}", self.reg(y, false)),
+                // For LD (IX+d),n the displacement comes before n
+                6 => {
+                    let reg = self.reg(y, false);
+                    format!("LD {},{}", reg, self.byte())
+                }
+                _ => ACCUMULATOR_OPS[y as usize].to_string(),
+            },
+            1 if opcode == 0x76 => "HALT".to_string(),
+            1 => {
+                let memory_operand = y == 6 || z == 6;
+                let dest = self.reg(y, memory_operand);
+                let src = self.reg(z, memory_operand);
+                format!("LD {},{}", dest, src)
+            }
+            2 => format!("{}{}", ALU_OPS[y as usize], self.reg(z, false)),
+            _ => match z {
+                0 => format!("RET {}", CONDITIONS[y as usize]),
+                1 if q == 0 => format!("POP {}", self.pair_af(p)),
+                1 => match p {
+                    0 => "RET".to_string(),
+                    1 => "EXX".to_string(),
+                    2 => format!("JP ({})", self.pair(2)),
+                    _ => format!("LD SP,{}", self.pair(2)),
+                },
+                2 => format!("JP {},{}", CONDITIONS[y as usize], self.word()),
+                3 => match y {
+                    0 => format!("JP {}", self.word()),
+                    2 => format!("OUT ({}),A", self.byte()),
+                    3 => format!("IN A,({})", self.byte()),
+                    4 => format!("EX (SP),{}", self.pair(2)),
+                    5 => "EX DE,HL".to_string(),
+                    6 => "DI".to_string(),
+                    _ => "EI".to_string(),
+                },
+                4 => format!("CALL {},{}", CONDITIONS[y as usize], self.word()),
+                5 if q == 0 => format!("PUSH {}", self.pair_af(p)),
+                5 => format!("CALL {}", self.word()),
+                6 => format!("{}{}", ALU_OPS[y as usize], self.byte()),
+                _ => format!("RST ${:02X}", y * 8),
+            },
+        }
+    }
+
+    fn cb_instruction(&mut self, opcode: u8) -> String {
+        let y = (opcode >> 3) & 0x07;
+        let reg = REGS[(opcode & 0x07) as usize];
+        match opcode >> 6 {
+            0 => format!("{} {}", ROTATIONS[y as usize], reg),
+            1 => format!("BIT {},{}", y, reg),
+            2 => format!("RES {},{}", y, reg),
+            _ => format!("SET {},{}", y, reg),
+        }
+    }
+
+    // DDCB/FDCB: the displacement comes before the opcode. Undocumented:
+    // unless the register field is 6, the result is also copied to that
+    // register, shown as a third operand.
+    fn index_cb_instruction(&mut self, index: &str) -> String {
+        let operand = self.indexed(index);
+        let opcode = self.next();
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let copy = if z == 6 {
+            String::new()
+        } else {
+            format!(",{}", REGS[z as usize])
+        };
+
+        match opcode >> 6 {
+            0 => format!("{} {}{}", ROTATIONS[y as usize], operand, copy),
+            1 => format!("BIT {},{}", y, operand),
+            2 => format!("RES {},{}{}", y, operand, copy),
+            _ => format!("SET {},{}{}", y, operand, copy),
+        }
+    }
+
+    fn ed_instruction(&mut self, opcode: u8) -> String {
+        let y = (opcode >> 3) & 0x07;
+        let p = y >> 1;
+        let q = y & 1;
+
+        match opcode {
+            0x40..=0x7F => match opcode & 0x07 {
+                0 if y == 6 => "IN F,(C)".to_string(),
+                0 => format!("IN {},(C)", REGS[y as usize]),
+                1 if y == 6 => "OUT (C),0".to_string(),
+                1 => format!("OUT (C),{}", REGS[y as usize]),
+                2 if q == 0 => format!("SBC HL,{}", PAIRS[p as usize]),
+                2 => format!("ADC HL,{}", PAIRS[p as usize]),
+                3 if q == 0 => format!("LD ({}),{}", self.word(), PAIRS[p as usize]),
+                3 => format!("LD {},({})", PAIRS[p as usize], self.word()),
+                4 => "NEG".to_string(),
+                5 if y == 1 => "RETI".to_string(),
+                5 => "RETN".to_string(),
+                6 => format!("IM {}", INTERRUPT_MODES[(y & 0x03) as usize]),
+                _ => match y {
+                    0 => "LD I,A".to_string(),
+                    1 => "LD R,A".to_string(),
+                    2 => "LD A,I".to_string(),
+                    3 => "LD A,R".to_string(),
+                    4 => "RRD".to_string(),
+                    5 => "RLD".to_string(),
+                    _ => "NOP".to_string(),
+                },
+            },
+            0xA0..=0xA3 | 0xA8..=0xAB | 0xB0..=0xB3 | 0xB8..=0xBB => {
+                BLOCK_OPS[(y - 4) as usize][(opcode & 0x03) as usize].to_string()
+            }
+            // Undefined ED opcodes execute as two-byte NOPs
+            _ => "NOP".to_string(),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Flag bit positions
+pub(super) const FLAG_C: u8 = 0x01; // Carry
+pub(super) const FLAG_N: u8 = 0x02; // Add/Subtract
+pub(super) const FLAG_PV: u8 = 0x04; // Parity/Overflow
+pub(super) const FLAG_Y: u8 = 0x08; // Undocumented (bit 3 of result)
+pub(super) const FLAG_H: u8 = 0x10; // Half Carry
+pub(super) const FLAG_X: u8 = 0x20; // Undocumented (bit 5 of result)
+pub(super) const FLAG_Z: u8 = 0x40; // Zero
+pub(super) const FLAG_S: u8 = 0x80; // Sign
+
+// S, Z, 5 and 3 for every result, and the same with parity in P/V
+pub(super) const SZ53: [u8; 256] = sz53_table(false);
+pub(super) const SZ53P: [u8; 256] = sz53_table(true);
+
+// Half carry and overflow for 8-bit adds and subtracts, indexed by bit 3
+// (or bit 7) of the two operands and the result. See half_carry_index().
+pub(super) const HALF_CARRY_ADD: [u8; 8] = [0, FLAG_H, FLAG_H, FLAG_H, 0, 0, 0, FLAG_H];
+pub(super) const HALF_CARRY_SUB: [u8; 8] = [0, 0, FLAG_H, 0, FLAG_H, 0, FLAG_H, FLAG_H];
+pub(super) const OVERFLOW_ADD: [u8; 8] = [0, 0, 0, FLAG_PV, FLAG_PV, 0, 0, 0];
+pub(super) const OVERFLOW_SUB: [u8; 8] = [0, FLAG_PV, 0, 0, 0, 0, FLAG_PV, 0];
+
+const fn sz53_table(parity: bool) -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let val = i as u8;
+        let mut flags = val & (FLAG_S | FLAG_X | FLAG_Y);
+        if val == 0 {
+            flags |= FLAG_Z;
+        }
+        if parity && val.count_ones().is_multiple_of(2) {
+            flags |= FLAG_PV;
+        }
+        table[i] = flags;
+        i += 1;
+    }
+    table
+}
+
+// Packs bit 3 of a, b and the result into bits 0-2, and bit 7 of each into
+// bits 4-6. The low three bits index the half carry tables and the high
+// three the overflow tables.
+#[inline]
+pub(super) fn half_carry_index(a: u8, b: u8, result: u8) -> usize {
+    (((a & 0x88) >> 3) | ((b & 0x88) >> 2) | ((result & 0x88) >> 1)) as usize
+}
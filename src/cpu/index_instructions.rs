@@ -0,0 +1,202 @@
+use super::{Bus, Cpu};
+
+// The DD and FD prefixes select IX or IY. Both share this implementation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum IndexReg {
+    Ix,
+    Iy,
+}
+
+impl IndexReg {
+    fn prefix(self) -> u8 {
+        match self {
+            IndexReg::Ix => 0xDD,
+            IndexReg::Iy => 0xFD,
+        }
+    }
+}
+
+impl Cpu {
+    #[inline]
+    fn index(&self, xy: IndexReg) -> u16 {
+        match xy {
+            IndexReg::Ix => self.ix,
+            IndexReg::Iy => self.iy,
+        }
+    }
+
+    #[inline]
+    fn set_index(&mut self, xy: IndexReg, val: u16) {
+        match xy {
+            IndexReg::Ix => self.ix = val,
+            IndexReg::Iy => self.iy = val,
+        }
+    }
+
+    pub(super) fn execute_index_instruction<B: Bus>(
+        &mut self,
+        xy: IndexReg,
+        opcode: u8,
+        bus: &mut B,
+    ) {
+        match opcode {
+            // INC (IX+d), DEC (IX+d)
+            0x34 | 0x35 => {
+                let addr = self.index_address(xy, bus);
+                let val = self.read_byte(bus, addr);
+                self.internal(bus, addr, 1);
+                let result = if opcode == 0x34 {
+                    self.inc8(val)
+                } else {
+                    self.dec8(val)
+                };
+                self.write_byte(bus, addr, result);
+            }
+            // LD (IX+d),n - n follows d, so the address is worked out after it
+            0x36 => {
+                let d = self.fetch_byte(bus) as i8;
+                let n = self.fetch_byte(bus);
+                self.internal(bus, self.pc.wrapping_sub(1), 2);
+                let addr = self.index(xy).wrapping_add(d as u16);
+                self.wz = addr;
+                self.write_byte(bus, addr, n);
+            }
+            // LD r,(IX+d) and LD (IX+d),r use the real H and L
+            0x46 | 0x4E | 0x56 | 0x5E | 0x66 | 0x6E | 0x7E => {
+                let addr = self.index_address(xy, bus);
+                let val = self.read_byte(bus, addr);
+                self.write_reg((opcode >> 3) & 0x07, val, bus);
+            }
+            0x70..=0x75 | 0x77 => {
+                let addr = self.index_address(xy, bus);
+                let val = self.read_reg(opcode & 0x07, bus);
+                self.write_byte(bus, addr, val);
+            }
+            // ALU A,(IX+d)
+            0x86 | 0x8E | 0x96 | 0x9E | 0xA6 | 0xAE | 0xB6 | 0xBE => {
+                let addr = self.index_address(xy, bus);
+                let val = self.read_byte(bus, addr);
+                self.alu((opcode >> 3) & 0x07, val);
+            }
+            0xCB => {
+                // The displacement comes before the opcode, and neither is
+                // read in an M1 cycle
+                let d = self.fetch_byte(bus) as i8;
+                let sub_opcode = self.fetch_byte(bus);
+                self.internal(bus, self.pc.wrapping_sub(1), 2);
+                self.execute_index_cb_instruction(xy, sub_opcode, d, bus)
+            }
+            // Another index prefix replaces this one, which acts as a NOP.
+            // No interrupt may be accepted before its opcode is fetched.
+            0xDD | 0xFD => {
+                self.set_unknown_opcode(xy.prefix(), opcode);
+                self.pending_prefix = Some(opcode);
+                self.int_blocked = true;
+            }
+            _ if uses_hl(opcode) => self.index_as_hl(xy, opcode, bus),
+            // The prefix has no effect on any other opcode, which runs as if
+            // unprefixed
+            _ => {
+                self.set_unknown_opcode(xy.prefix(), opcode);
+                self.execute_instruction(opcode, bus)
+            }
+        }
+    }
+
+    // Fetch d and form IX+d. Adding the displacement takes 5 T-states.
+    fn index_address<B: Bus>(&mut self, xy: IndexReg, bus: &mut B) -> u16 {
+        let d = self.fetch_byte(bus) as i8;
+        let addr = self.index(xy).wrapping_add(d as u16);
+        self.wz = addr;
+        self.internal(bus, self.pc.wrapping_sub(1), 5);
+        addr
+    }
+
+    // Execute an unprefixed HL instruction with the index register standing
+    // in for HL, so H and L become IXH and IXL (or IYH and IYL)
+    fn index_as_hl<B: Bus>(&mut self, xy: IndexReg, opcode: u8, bus: &mut B) {
+        let hl = self.hl();
+        self.set_hl(self.index(xy));
+        self.execute_instruction(opcode, bus);
+        self.set_index(xy, self.hl());
+        self.set_hl(hl);
+    }
+
+    // DDCB/FDCB opcodes always operate on (IX+d), read and then held for a
+    // T-state. Undocumented: unless the register field is 6, the result is
+    // also copied into that register.
+    fn execute_index_cb_instruction<B: Bus>(
+        &mut self,
+        xy: IndexReg,
+        opcode: u8,
+        d: i8,
+        bus: &mut B,
+    ) {
+        let y = (opcode >> 3) & 0x07;
+        let reg = opcode & 0x07;
+        let addr = self.index(xy).wrapping_add(d as u16);
+        self.wz = addr;
+        let val = self.read_byte(bus, addr);
+        self.internal(bus, addr, 1);
+
+        let result = match opcode >> 6 {
+            0 => self.rotate_shift(y, val),
+            // Undocumented flags come from the high byte of the address
+            1 => return self.bit(y, val, (addr >> 8) as u8),
+            2 => val & !(1 << y),
+            3 => val | (1 << y),
+            _ => unreachable!(),
+        };
+
+        self.write_byte(bus, addr, result);
+        if reg != 6 {
+            self.write_reg(reg, result, bus);
+        }
+    }
+}
+
+// Opcodes that use H, L or HL but not (HL). With an index prefix they
+// operate on the index register instead. EX DE,HL and EXX are unaffected.
+fn uses_hl(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x09 | 0x19
+            | 0x21..=0x26
+            | 0x29..=0x2E
+            | 0x39
+            | 0x44
+            | 0x45
+            | 0x4C
+            | 0x4D
+            | 0x54
+            | 0x55
+            | 0x5C
+            | 0x5D
+            | 0x60..=0x65
+            | 0x67..=0x6D
+            | 0x6F
+            | 0x7C
+            | 0x7D
+            | 0x84
+            | 0x85
+            | 0x8C
+            | 0x8D
+            | 0x94
+            | 0x95
+            | 0x9C
+            | 0x9D
+            | 0xA4
+            | 0xA5
+            | 0xAC
+            | 0xAD
+            | 0xB4
+            | 0xB5
+            | 0xBC
+            | 0xBD
+            | 0xE1
+            | 0xE3
+            | 0xE5
+            | 0xE9
+            | 0xF9
+    )
+}
@@ -0,0 +1,64 @@
+use super::{Bus, Cpu};
+
+impl Cpu {
+    // Accept a maskable interrupt. Called from step() when /INT is active and
+    // IFF1 is set, in place of fetching the next opcode.
+    pub(super) fn accept_interrupt<B: Bus>(&mut self, bus: &mut B) {
+        self.iff1 = false;
+        self.iff2 = false;
+        self.leave_halt();
+
+        // The acknowledge cycle replaces the opcode fetch and adds 2 wait
+        // states to it
+        self.interrupt_ack_cycle(bus);
+        let data = bus.interrupt_data();
+
+        match self.interrupt_mode {
+            // Execute whatever instruction the device places on the bus -
+            // normally an RST (13 T-states in all). PC isn't advanced past
+            // it. Only single-byte instructions are supported: the rest of a
+            // longer one is read from memory at PC.
+            0 => self.execute_instruction(data, bus),
+            // RST 38h (13 T-states)
+            1 => {
+                self.internal(bus, self.ir(), 1);
+                self.push(self.pc, bus);
+                self.pc = 0x0038;
+                self.wz = self.pc;
+            }
+            // Jump through the vector table at I * 256 + bus byte (19 T-states)
+            2 => {
+                self.internal(bus, self.ir(), 1);
+                let vector = ((self.i as u16) << 8) | data as u16;
+                self.push(self.pc, bus);
+                self.pc = self.read_word(bus, vector);
+                self.wz = self.pc;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Accept a non-maskable interrupt. IFF1 is saved in IFF2 so RETN can
+    // restore it, then the CPU calls 0x0066 (11 T-states).
+    pub(super) fn accept_nmi<B: Bus>(&mut self, bus: &mut B) {
+        self.nmi_pending = false;
+        self.iff2 = self.iff1;
+        self.iff1 = false;
+        self.leave_halt();
+
+        // The opcode fetched here is ignored
+        self.opcode_cycle(bus, self.pc);
+        self.internal(bus, self.ir(), 1);
+        self.push(self.pc, bus);
+        self.pc = 0x0066;
+        self.wz = self.pc;
+    }
+
+    // An interrupt resumes execution at the instruction following the HALT
+    fn leave_halt(&mut self) {
+        if self.is_halted {
+            self.is_halted = false;
+            self.pc = self.pc.wrapping_add(1);
+        }
+    }
+}
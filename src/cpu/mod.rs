@@ -1,12 +1,60 @@
-use crate::memory::Memory;
-
+mod alu;
+mod bus;
 mod cb_instructions;
-mod dd_instructions;
+mod cycles;
+pub mod disasm;
 mod ed_instructions;
-mod fd_instructions;
+mod flags;
+mod index_instructions;
 mod instructions;
+mod interrupts;
 mod registers;
 
+pub use bus::{Bus, Cycle};
+
+use flags::FLAG_PV;
+
+// Chip families differ in a few undocumented behaviours: flags 3 and 5
+// after SCF/CCF, what OUT (C),0 outputs and whether LD A,I/R can read P/V
+// wrongly when interrupted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CpuVariant {
+    // Zilog Z80 (NMOS), as fitted to most Spectrums
+    #[default]
+    ZilogNmos,
+    // NEC D780C (NMOS), found in some later machines and clones
+    NecNmos,
+    // Zilog Z84C00 (CMOS)
+    Cmos,
+}
+
+// An opcode with no documented meaning: an undefined ED opcode, which runs
+// as a NOP, or a DD/FD prefix with no effect on the opcode after it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownOpcode {
+    // Address of the prefix
+    pub addr: u16,
+    pub prefix: u8,
+    pub opcode: u8,
+}
+
+// What the last step did, so a trace can tell instructions from interrupts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepKind {
+    // Fetched and ran the instruction at PC
+    Instruction,
+    // Ran the instruction after a DD/FD prefix the previous step stopped at,
+    // starting with that prefix at PC - 1
+    Prefix,
+    // Accepted an NMI
+    Nmi,
+    // Accepted a maskable interrupt, in the current interrupt mode
+    Interrupt,
+    // Fetched and ignored an opcode while halted
+    Halted,
+}
+
+#[derive(Clone)]
 pub struct Cpu {
     // Z80 CPU @ 3.5MHz (Spectrum vs 3.25MHz ZX81)
     // Registers
@@ -40,16 +88,61 @@ pub struct Cpu {
     // Interrupt mode (0, 1, or 2)
     pub interrupt_mode: u8,
 
+    // Maskable interrupt request line (/INT), driven by the ULA
+    pub int_line: bool,
+
+    // Latched falling edge on /NMI, cleared once the NMI is serviced
+    pub nmi_pending: bool,
+
+    // Set by EI and by a DD/FD prefix still waiting for its opcode: a
+    // maskable interrupt can't be accepted until the following instruction
+    // has run. This lets handlers finish with EI; RET safely.
+    pub int_blocked: bool,
+
+    // A DD/FD prefix fetched straight after another. The first acts as a NOP
+    // and this one applies to the opcode fetched by the next step.
+    pub pending_prefix: Option<u8>,
+
     // Stack pointer and program counter
     pub sp: u16,
     pub pc: u16,
 
+    // MEMPTR (WZ): internal register holding intermediate addresses. It can
+    // only be observed through flags 3 and 5 after BIT n,(HL).
+    pub wz: u16,
+
+    // Q: a copy of F if the last instruction modified the flags, else zero.
+    // SCF and CCF leak it into flags 3 and 5.
+    pub q: u8,
+    flags_modified: bool,
+
+    // Which chip's undocumented behaviour to follow
+    pub variant: CpuVariant,
+
+    // Set by LD A,I and LD A,R. On NMOS parts, an interrupt accepted
+    // straight after them resets the P/V flag they set from IFF2.
+    ld_a_ir: bool,
+
+    // T-states used so far by the instruction being executed
+    tstates: u32,
+
+    // Set if the last step ran an unknown opcode
+    unknown_opcode: Option<UnknownOpcode>,
+
+    last_step: StepKind,
+
     // Holds whether the CPU is halted
     pub is_halted: bool,
 }
 
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new(CpuVariant::default())
+    }
+}
+
 impl Cpu {
-    pub fn new() -> Self {
+    pub fn new(variant: CpuVariant) -> Self {
         Self {
             a: 0,
             f: 0,
@@ -70,43 +163,167 @@ impl Cpu {
             iff1: false,
             iff2: false,
             interrupt_mode: 0,
+            int_line: false,
+            nmi_pending: false,
+            int_blocked: false,
+            pending_prefix: None,
             sp: 0xFFFF,
             pc: 0x0000,
+            wz: 0x0000,
+            q: 0,
+            flags_modified: false,
+            variant,
+            ld_a_ir: false,
+            tstates: 0,
+            unknown_opcode: None,
+            last_step: StepKind::Instruction,
             is_halted: false,
         }
     }
 
-    fn fetch_byte(&mut self, memory: &Memory) -> u8 {
-        let addr = self.pc;
-        let byte = memory.read(addr);
+    // Pull /RESET: PC, I and R are cleared, interrupts disabled and IM 0
+    // selected. Every other register keeps its value.
+    pub fn reset(&mut self) {
+        self.pc = 0x0000;
+        self.i = 0;
+        self.r = 0;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.interrupt_mode = 0;
+        self.nmi_pending = false;
+        self.int_blocked = false;
+        self.pending_prefix = None;
+        self.ld_a_ir = false;
+        self.unknown_opcode = None;
+        self.is_halted = false;
+    }
+
+    // Switch on. The registers come up holding whatever they settle to,
+    // taken from fill, except AF and SP, which read 0xFFFF on real chips.
+    // Then the CPU is reset as the power-on reset circuit would.
+    pub fn power_on(&mut self, mut fill: impl FnMut() -> u8) {
+        let mut word = || u16::from_le_bytes([fill(), fill()]);
+        let (bc, de, hl) = (word(), word(), word());
+        self.af_shadow = word();
+        self.bc_shadow = word();
+        self.de_shadow = word();
+        self.hl_shadow = word();
+        self.ix = word();
+        self.iy = word();
+        self.wz = word();
+        self.set_bc(bc);
+        self.set_de(de);
+        self.set_hl(hl);
+        self.set_af(0xFFFF);
+        self.sp = 0xFFFF;
+        self.q = 0;
+        self.reset();
+    }
+
+    fn fetch_byte<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let byte = self.read_byte(bus, self.pc);
         self.pc = self.pc.wrapping_add(1);
         byte
     }
 
-    fn fetch_word(&mut self, memory: &Memory) -> u16 {
-        let lo = self.fetch_byte(memory) as u16;
-        let hi = self.fetch_byte(memory) as u16;
+    // Opcode bytes, including those after a CB/DD/ED/FD prefix, are read
+    // in M1 cycles
+    fn fetch_opcode<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let byte = self.opcode_cycle(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        byte
+    }
+
+    fn fetch_word<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let lo = self.fetch_byte(bus) as u16;
+        let hi = self.fetch_byte(bus) as u16;
         (hi << 8) | lo
     }
 
-    pub fn step(&mut self, memory: &mut Memory) -> u8 {
-        // Increment refresh register (R) on each M1 cycle
-        self.r = (self.r & 0x80) | ((self.r + 1) & 0x7F);
+    // Run one instruction, interrupt response or halted NOP. Returns the
+    // T-states taken, as the sum of the machine cycles that ran.
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        self.tstates = 0;
+        self.unknown_opcode = None;
 
-        // Is the system halted?
-        if self.is_halted {
-            // Return 4 T-states for a NOP-equivalent halt cycle
-            return 4;
-        }
+        // Interrupts are sampled before the next opcode fetch, NMI first
+        let int_blocked = self.int_blocked;
+        self.int_blocked = false;
+        let ld_a_ir = self.ld_a_ir;
+        self.ld_a_ir = false;
+
+        self.flags_modified = false;
+        self.last_step = if let Some(prefix) = self.pending_prefix.take() {
+            // Nothing can interrupt between a prefix and its opcode
+            self.execute_instruction(prefix, bus);
+            StepKind::Prefix
+        } else if self.nmi_pending {
+            self.accept_nmi(bus);
+            StepKind::Nmi
+        } else if self.int_line && self.iff1 && !int_blocked {
+            // NMOS parts reset IFF2 before LD A,I/R has finished copying
+            // it into P/V, so the flag reads 0
+            if ld_a_ir && self.variant != CpuVariant::Cmos {
+                self.f &= !FLAG_PV;
+            }
+            self.accept_interrupt(bus);
+            StepKind::Interrupt
+        } else if self.is_halted {
+            // HALT keeps fetching (and ignoring) the next opcode
+            self.opcode_cycle(bus, self.pc.wrapping_add(1));
+            StepKind::Halted
+        } else {
+            // Retrieve the opcode at the current program counter
+            // PC is incremented in fetch_opcode automatically
+            let opcode = self.fetch_opcode(bus);
+            self.execute_instruction(opcode, bus);
+            StepKind::Instruction
+        };
 
-        // Retrieve the opcode at the current program counter
-        // PC is incremented in fetch_byte automatically
-        let opcode = self.fetch_byte(memory);
-        let cycles = self.execute(opcode, memory);
-        cycles
+        self.q = if self.flags_modified { self.f } else { 0 };
+        self.tstates
+    }
+
+    pub fn last_step(&self) -> StepKind {
+        self.last_step
+    }
+
+    // The unknown opcode run by the last step, if any
+    pub fn unknown_opcode(&self) -> Option<UnknownOpcode> {
+        self.unknown_opcode
+    }
+
+    // Note an unknown opcode just fetched, following a prefix
+    fn set_unknown_opcode(&mut self, prefix: u8, opcode: u8) {
+        self.unknown_opcode = Some(UnknownOpcode {
+            addr: self.pc.wrapping_sub(2),
+            prefix,
+            opcode,
+        });
+    }
+
+    // Pulse /NMI. The interrupt is taken before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Run a number of halted NOP cycles in one go, as step() would one at a
+    // time. Each is a full M1 cycle on the bus, so contention and ticks are
+    // the same. Returns the T-states taken.
+    pub fn run_halted<B: Bus>(&mut self, bus: &mut B, nops: u32) -> u32 {
+        self.tstates = 0;
+        self.unknown_opcode = None;
+        self.last_step = StepKind::Halted;
+        for _ in 0..nops {
+            self.opcode_cycle(bus, self.pc.wrapping_add(1));
+        }
+        self.q = 0;
+        self.tstates
     }
 
-    fn execute(&mut self, opcode: u8, memory: &mut Memory) -> u8 {
-        self.execute_instruction(opcode, memory)
+    // Only the bottom 7 bits of R count; bit 7 keeps whatever LD R,A set
+    pub(super) fn increment_r(&mut self, count: u32) {
+        let low = (self.r as u32).wrapping_add(count) as u8 & 0x7F;
+        self.r = (self.r & 0x80) | low;
     }
 }
@@ -1,21 +1,12 @@
-use super::Cpu;
-use crate::memory::Memory;
-
-// Flag bit positions
-const FLAG_C: u8 = 0x01; // Carry
-const FLAG_N: u8 = 0x02; // Add/Subtract
-const FLAG_PV: u8 = 0x04; // Parity/Overflow
-const FLAG_Y: u8 = 0x08; // Undocumented (bit 3 of result)
-const FLAG_H: u8 = 0x10; // Half Carry
-const FLAG_X: u8 = 0x20; // Undocumented (bit 5 of result)
-const FLAG_Z: u8 = 0x40; // Zero
-const FLAG_S: u8 = 0x80; // Sign
+use super::flags::{FLAG_C, FLAG_H, FLAG_N, FLAG_PV, FLAG_S, FLAG_X, FLAG_Y, FLAG_Z};
+use super::{Bus, Cpu};
 
 // Register and flag helper methods
 impl Cpu {
     // == Flag-setting helper functions == //
     #[inline]
     pub fn set_flag_c(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_C;
         } else {
@@ -25,6 +16,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_n(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_N;
         } else {
@@ -34,6 +26,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_pv(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_PV;
         } else {
@@ -43,6 +36,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_y(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_Y;
         } else {
@@ -52,6 +46,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_h(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_H;
         } else {
@@ -61,6 +56,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_x(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_X;
         } else {
@@ -70,6 +66,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_z(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_Z;
         } else {
@@ -79,6 +76,7 @@ impl Cpu {
 
     #[inline]
     pub fn set_flag_s(&mut self, val: bool) {
+        self.flags_modified = true;
         if val {
             self.f |= FLAG_S;
         } else {
@@ -86,6 +84,13 @@ impl Cpu {
         }
     }
 
+    // Replace all of F at once, as the table-driven ALU operations do
+    #[inline]
+    pub(super) fn set_flags(&mut self, flags: u8) {
+        self.flags_modified = true;
+        self.f = flags;
+    }
+
     // == Flag-getting helper functions == //
     #[inline]
     pub fn get_flag_c(&self) -> bool {
@@ -172,9 +177,47 @@ impl Cpu {
         self.l = val as u8;
     }
 
+    // Register pairs as selected by bits 4-5 of an opcode: BC, DE, HL, then
+    // SP for most instructions (rp) or AF for PUSH and POP (rp2)
+    #[inline]
+    pub(super) fn rp(&self, p: u8) -> u16 {
+        match p {
+            0 => self.bc(),
+            1 => self.de(),
+            2 => self.hl(),
+            3 => self.sp,
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn set_rp(&mut self, p: u8, val: u16) {
+        match p {
+            0 => self.set_bc(val),
+            1 => self.set_de(val),
+            2 => self.set_hl(val),
+            3 => self.sp = val,
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn rp2(&self, p: u8) -> u16 {
+        if p == 3 { self.af() } else { self.rp(p) }
+    }
+
+    #[inline]
+    pub(super) fn set_rp2(&mut self, p: u8, val: u16) {
+        if p == 3 {
+            self.set_af(val);
+        } else {
+            self.set_rp(p, val);
+        }
+    }
+
     // == Read & Write to registers == //
     #[inline]
-    pub fn read_reg(&self, reg_code: u8, memory: &Memory) -> u8 {
+    pub fn read_reg<B: Bus>(&mut self, reg_code: u8, bus: &mut B) -> u8 {
         match reg_code {
             0 => self.b,
             1 => self.c,
@@ -182,14 +225,14 @@ impl Cpu {
             3 => self.e,
             4 => self.h,
             5 => self.l,
-            6 => memory.read(self.hl()), // (HL) - special case
+            6 => self.read_byte(bus, self.hl()), // (HL) - special case
             7 => self.a,
             _ => unreachable!(),
         }
     }
 
     #[inline]
-    pub fn write_reg(&mut self, reg_code: u8, val: u8, memory: &mut Memory) {
+    pub fn write_reg<B: Bus>(&mut self, reg_code: u8, val: u8, bus: &mut B) {
         match reg_code {
             0 => self.b = val,
             1 => self.c = val,
@@ -197,22 +240,36 @@ impl Cpu {
             3 => self.e = val,
             4 => self.h = val,
             5 => self.l = val,
-            6 => memory.write(self.hl(), val), // (HL) - special case
+            6 => self.write_byte(bus, self.hl(), val), // (HL) - special case
             7 => self.a = val,
             _ => unreachable!(),
         }
     }
 
+    // Read an operand that will be modified and written back. (HL) is held
+    // for a T-state while the result is worked out.
+    #[inline]
+    pub(super) fn read_operand<B: Bus>(&mut self, reg_code: u8, bus: &mut B) -> u8 {
+        let val = self.read_reg(reg_code, bus);
+        if reg_code == 6 {
+            self.internal(bus, self.hl(), 1);
+        }
+        val
+    }
+
     // == Stack operations == //
+    // The high byte is pushed first, as the stack grows down
     #[inline]
-    pub fn push(&mut self, val: u16, memory: &mut Memory) {
-        self.sp = self.sp.wrapping_sub(2);
-        memory.write_word(self.sp, val);
+    pub fn push<B: Bus>(&mut self, val: u16, bus: &mut B) {
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(bus, self.sp, (val >> 8) as u8);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(bus, self.sp, val as u8);
     }
 
     #[inline]
-    pub fn pop(&mut self, memory: &Memory) -> u16 {
-        let val = memory.read_word(self.sp);
+    pub fn pop<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let val = self.read_word(bus, self.sp);
         self.sp = self.sp.wrapping_add(2);
         val
     }
@@ -1,52 +1,222 @@
-use crate::cpu::Cpu;
-use crate::memory::Memory;
+use std::error::Error;
+use std::fmt;
+
+use crate::cpu::{Bus, Cpu, CpuVariant, UnknownOpcode};
+use crate::io::{IoBus, IoController, IoDevice};
+use crate::memory::{Memory, MemoryBus};
+use crate::trace::{Tracer, next_instruction};
 use crate::video::Video;
 
 pub struct Emulator {
     cpu: Cpu,
     memory: Memory,
+    io: IoController,
     video: Video,
     cycles: u64,
     frame_cycles: u64,
+    tracer: Option<Tracer>,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+}
+
+// What to do when the CPU runs an opcode with no documented meaning
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    // Carry on as the hardware does
+    #[default]
+    Nop,
+    // Fail with EmulatorEvent::UnknownOpcode
+    Stop,
+    // Dump the trace and return EmulatorEvent::Break, so the caller can
+    // pause and show the debugger
+    Break,
+}
+
+// What the registers and RAM hold at switch-on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerOnState {
+    // Every byte reads 0xFF
+    Ones,
+    // Pseudo-random bytes from a seed, so a run can be repeated
+    Random(u64),
+}
+
+// Why step() or run_frame() stopped. The instruction has already run, so
+// stepping again carries on from the one after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    UnknownOpcode(UnknownOpcode),
+    Break(UnknownOpcode),
+}
+
+impl fmt::Display for EmulatorEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, op) = match self {
+            EmulatorEvent::UnknownOpcode(op) => ("Unknown opcode", op),
+            EmulatorEvent::Break(op) => ("Break on unknown opcode", op),
+        };
+        write!(
+            f,
+            "{} {:02X} {:02X} at 0x{:04X}",
+            kind, op.prefix, op.opcode, op.addr
+        )
+    }
+}
+
+impl Error for EmulatorEvent {}
+
+impl UnknownOpcodePolicy {
+    // The event to stop for when the CPU has just run op, if any
+    pub fn event(self, op: UnknownOpcode) -> Option<EmulatorEvent> {
+        match self {
+            UnknownOpcodePolicy::Nop => None,
+            UnknownOpcodePolicy::Stop => Some(EmulatorEvent::UnknownOpcode(op)),
+            UnknownOpcodePolicy::Break => Some(EmulatorEvent::Break(op)),
+        }
+    }
 }
 
 // Spectrum timing constants
 const CYCLES_PER_FRAME: u64 = 69888; // 3.5MHz / 50H
+const INT_LENGTH: u64 = 32; // ULA holds /INT active for 32 T-states per frame
 
 // This var will be required later
 #[allow(dead_code)]
 const CYCLES_PER_SCANLINE: u64 = 224; // 69888 / 312 lines
 
 impl Emulator {
-    pub fn new(rom: Vec<u8>, debug_enabled: bool) -> Result<Self, minifb::Error> {
+    pub fn new(
+        rom: Vec<u8>,
+        variant: CpuVariant,
+        debug_enabled: bool,
+    ) -> Result<Self, minifb::Error> {
         Ok(Self {
-            cpu: Cpu::new(),
+            cpu: Cpu::new(variant),
             memory: Memory::new(rom),
+            io: IoController::new(),
             video: Video::new(debug_enabled)?,
             cycles: 0,
             frame_cycles: 0,
+            tracer: None,
+            unknown_opcode_policy: UnknownOpcodePolicy::Nop,
         })
     }
 
-    pub fn step(&mut self) -> u8 {
-        let cycles = self.cpu.step(&mut self.memory);
+    // The reset button: resets the CPU and peripherals. RAM keeps its
+    // contents and the ULA keeps its place in the frame.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.io.reset();
+    }
+
+    // Switch the machine off and on again
+    pub fn power_on(&mut self, state: PowerOnState) {
+        let mut fill = power_on_fill(state);
+        self.cpu.power_on(&mut fill);
+        self.memory.power_on(&mut fill);
+        self.io.reset();
+        self.cycles = 0;
+        self.frame_cycles = 0;
+    }
+
+    // Run one instruction. Returns the T-states taken, or the event that
+    // the unknown opcode policy stopped for.
+    pub fn step(&mut self) -> Result<u32, EmulatorEvent> {
+        // The ULA raises /INT at the start of each frame
+        self.cpu.int_line = self.frame_cycles < INT_LENGTH;
+
+        let halted = self.is_halted() && !self.cpu.int_line && !self.cpu.nmi_pending;
+        let cycles = if halted && self.tracer.is_none() {
+            // Nothing can happen until the next frame's interrupt, so run
+            // the NOPs HALT would execute up to that point all at once. A
+            // trace wants a line for each, so it steps through them instead.
+            let nops = (CYCLES_PER_FRAME - self.frame_cycles).div_ceil(4);
+            self.cpu.run_halted(
+                &mut SplitBus::new(&mut self.memory, &mut self.io),
+                nops as u32,
+            )
+        } else {
+            // The trace is written after the step, once it's known whether
+            // an instruction ran or an interrupt was accepted
+            let before = self
+                .tracer
+                .is_some()
+                .then(|| (self.cpu.clone(), next_instruction(&self.cpu, &self.memory)));
+            let cycles = self
+                .cpu
+                .step(&mut SplitBus::new(&mut self.memory, &mut self.io));
+            if let Some((cpu, instruction)) = before {
+                self.trace(&cpu, &instruction);
+            }
+            cycles
+        };
         self.cycles += cycles as u64;
         self.frame_cycles += cycles as u64;
 
         // Check if we've completed a frame
         if self.frame_cycles >= CYCLES_PER_FRAME {
             self.frame_cycles -= CYCLES_PER_FRAME;
-            // TODO: Generate interrupt here when interrupts are implemented
         }
 
-        cycles
+        match self.cpu.unknown_opcode() {
+            Some(op) => self.unknown_opcode(op).map(|_| cycles),
+            None => Ok(cycles),
+        }
+    }
+
+    fn unknown_opcode(&mut self, op: UnknownOpcode) -> Result<(), EmulatorEvent> {
+        let Some(event) = self.unknown_opcode_policy.event(op) else {
+            return Ok(());
+        };
+        self.dump_trace(&event.to_string());
+        Err(event)
     }
 
-    pub fn run_frame(&mut self) {
+    pub fn run_frame(&mut self) -> Result<(), EmulatorEvent> {
         let target_cycles = self.cycles + CYCLES_PER_FRAME;
 
-        while self.cycles < target_cycles && !self.cpu.is_halted {
-            self.step();
+        while self.cycles < target_cycles {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn unknown_opcode_policy(&self) -> UnknownOpcodePolicy {
+        self.unknown_opcode_policy
+    }
+
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.unknown_opcode_policy = policy;
+    }
+
+    // Log every instruction executed from now on through tracer
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // Write out the trace ring buffer, e.g. when the user asks for it
+    pub fn dump_trace(&mut self, reason: &str) {
+        if let Some(tracer) = &mut self.tracer
+            && let Err(e) = tracer.dump(reason)
+        {
+            eprintln!("Trace error: {}", e);
+            self.tracer = None;
+        }
+    }
+
+    // Log the step just run from the CPU state and instruction before it. A
+    // trace that can't be written is dropped rather than stopping the
+    // emulator.
+    fn trace(&mut self, before: &Cpu, instruction: &str) {
+        let step = self.cpu.last_step();
+        if let Some(tracer) = &mut self.tracer
+            && let Err(e) = tracer.trace(before, step, instruction, self.cycles)
+        {
+            eprintln!("Trace error: {}", e);
+            self.tracer = None;
         }
     }
 
@@ -66,6 +236,14 @@ impl Emulator {
         &mut self.memory
     }
 
+    pub fn io(&self) -> &IoController {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut IoController {
+        &mut self.io
+    }
+
     pub fn video(&self) -> &Video {
         &self.video
     }
@@ -74,6 +252,19 @@ impl Emulator {
         &mut self.video
     }
 
+    // Plug a peripheral into the expansion port. Besides its ports it can
+    // drive the data bus when an interrupt is acknowledged, e.g. to supply
+    // an IM 2 vector.
+    pub fn attach_device(&mut self, device: Box<dyn IoDevice>) {
+        self.io.attach(device);
+    }
+
+    // Press the NMI button; the CPU jumps to 0x0066 before its next instruction
+    pub fn trigger_nmi(&mut self) {
+        self.cpu.trigger_nmi();
+    }
+
+    // True while the CPU is sitting in a HALT waiting for an interrupt
     pub fn is_halted(&self) -> bool {
         self.cpu.is_halted
     }
@@ -91,17 +282,25 @@ impl Emulator {
     }
 
     pub fn render_display(&mut self) -> Result<(), minifb::Error> {
+        self.video.set_border_colour(self.io.ula().border_colour());
         self.video.render(&self.memory, &self.cpu);
         self.video.update()
     }
 
     pub fn update_keyboard(&mut self) {
         let keys = self.video.get_keys();
-        // TODO: Update keyboard state when I/O controller is implemented
-        let _ = keys;
+        let ula = self.io.ula_mut();
+
+        ula.release_all_keys();
+        for key in keys {
+            if let Some((row, bit)) = spectrum_key(key) {
+                ula.set_key(row, bit, true);
+            }
+        }
     }
 
     pub fn set_border_colour(&mut self, colour: u8) {
+        self.io.ula_mut().set_border_colour(colour);
         self.video.set_border_colour(colour);
     }
 
@@ -113,7 +312,7 @@ impl Emulator {
             let scan = line_in_third / 8;
             let pixel_line = line_in_third % 8;
 
-            let bitmap_addr = 0x4000 + (third << 11) | (pixel_line << 8) | (scan << 5) | x;
+            let bitmap_addr = 0x4000 + ((third << 11) | (pixel_line << 8) | (scan << 5) | x);
             self.memory.write(bitmap_addr as u16, pattern);
         }
     }
@@ -137,7 +336,7 @@ impl Emulator {
         println!("Frame cycles: {}/{}", self.frame_cycles, CYCLES_PER_FRAME);
         println!("CPU PC: 0x{:04X}", self.cpu.pc);
         println!("CPU SP: 0x{:04X}", self.cpu.sp);
-        println!("Border colour: {}", self.video_mut().border_colour());
+        println!("Border colour: {}", self.io.ula().border_colour());
 
         // Check screen memory
         let bitmap_start = 0x4000;
@@ -161,3 +360,115 @@ impl Emulator {
         println!("===================================\n");
     }
 }
+
+// A Bus built from separate memory and I/O halves, e.g. the Spectrum's
+// Memory and IoController, or a FlatRam for CP/M programs
+pub struct SplitBus<'a, M: MemoryBus, I: IoBus> {
+    pub memory: &'a mut M,
+    pub io: &'a mut I,
+}
+
+impl<'a, M: MemoryBus, I: IoBus> SplitBus<'a, M, I> {
+    pub fn new(memory: &'a mut M, io: &'a mut I) -> Self {
+        Self { memory, io }
+    }
+}
+
+impl<M: MemoryBus, I: IoBus> Bus for SplitBus<'_, M, I> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory.write(addr, val);
+    }
+
+    fn port_in(&mut self, port: u16) -> u8 {
+        self.io.port_in(port)
+    }
+
+    fn port_out(&mut self, port: u16, val: u8) {
+        self.io.port_out(port, val);
+    }
+
+    fn interrupt_data(&mut self) -> u8 {
+        self.io.interrupt_data()
+    }
+}
+
+// The byte source for a power-on state. Random uses xorshift64, which
+// needs a non-zero seed.
+fn power_on_fill(state: PowerOnState) -> impl FnMut() -> u8 {
+    let mut seed = match state {
+        PowerOnState::Ones => 0,
+        PowerOnState::Random(seed) => seed | 1,
+    };
+    move || {
+        if seed == 0 {
+            return 0xFF;
+        }
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 56) as u8
+    }
+}
+
+// Map a host key to its (half-row, bit) position in the Spectrum keyboard matrix
+fn spectrum_key(key: minifb::Key) -> Option<(usize, u8)> {
+    use minifb::Key;
+
+    let pos = match key {
+        // 0xFEFE: SHIFT, Z, X, C, V
+        Key::LeftShift | Key::RightShift => (0, 0),
+        Key::Z => (0, 1),
+        Key::X => (0, 2),
+        Key::C => (0, 3),
+        Key::V => (0, 4),
+        // 0xFDFE: A, S, D, F, G
+        Key::A => (1, 0),
+        Key::S => (1, 1),
+        Key::D => (1, 2),
+        Key::F => (1, 3),
+        Key::G => (1, 4),
+        // 0xFBFE: Q, W, E, R, T
+        Key::Q => (2, 0),
+        Key::W => (2, 1),
+        Key::E => (2, 2),
+        Key::R => (2, 3),
+        Key::T => (2, 4),
+        // 0xF7FE: 1, 2, 3, 4, 5
+        Key::Key1 => (3, 0),
+        Key::Key2 => (3, 1),
+        Key::Key3 => (3, 2),
+        Key::Key4 => (3, 3),
+        Key::Key5 => (3, 4),
+        // 0xEFFE: 0, 9, 8, 7, 6
+        Key::Key0 => (4, 0),
+        Key::Key9 => (4, 1),
+        Key::Key8 => (4, 2),
+        Key::Key7 => (4, 3),
+        Key::Key6 => (4, 4),
+        // 0xDFFE: P, O, I, U, Y
+        Key::P => (5, 0),
+        Key::O => (5, 1),
+        Key::I => (5, 2),
+        Key::U => (5, 3),
+        Key::Y => (5, 4),
+        // 0xBFFE: ENTER, L, K, J, H
+        Key::Enter => (6, 0),
+        Key::L => (6, 1),
+        Key::K => (6, 2),
+        Key::J => (6, 3),
+        Key::H => (6, 4),
+        // 0x7FFE: SPACE, SYM SHIFT, M, N, B
+        Key::Space => (7, 0),
+        Key::LeftCtrl | Key::RightCtrl => (7, 1),
+        Key::M => (7, 2),
+        Key::N => (7, 3),
+        Key::B => (7, 4),
+        _ => return None,
+    };
+
+    Some(pos)
+}
@@ -0,0 +1,25 @@
+// Kempston joystick interface (port 0x1F)
+pub struct Kempston {
+    // Active high: bit 0 right, 1 left, 2 down, 3 up, 4 fire
+    state: u8,
+}
+
+impl Kempston {
+    pub fn new() -> Self {
+        Self { state: 0 }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u8) {
+        self.state = state & 0x1F;
+    }
+}
+
+impl Default for Kempston {
+    fn default() -> Self {
+        Self::new()
+    }
+}
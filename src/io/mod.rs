@@ -0,0 +1,140 @@
+mod joystick;
+mod ula;
+pub use joystick::Kempston;
+pub use ula::Ula;
+
+// Port-mapped I/O as seen from the CPU.
+// The Z80 places the full 16-bit address on the bus for every IN/OUT: the low
+// byte comes from the instruction (or C) and the high byte from A (or B).
+pub trait IoBus {
+    fn port_in(&mut self, port: u16) -> u8;
+    fn port_out(&mut self, port: u16, val: u8);
+
+    // The byte read from the data bus when an interrupt is acknowledged
+    fn interrupt_data(&mut self) -> u8 {
+        0xFF
+    }
+}
+
+// Additional peripherals that can be attached to the I/O controller.
+// A device returns None from read() for ports it doesn't decode.
+pub trait IoDevice {
+    fn read(&mut self, port: u16) -> Option<u8>;
+    fn write(&mut self, port: u16, val: u8);
+
+    // What the device puts on the data bus when the CPU acknowledges an
+    // interrupt (an IM 2 vector or an IM 0 instruction), or None to leave
+    // it alone
+    fn interrupt_data(&mut self) -> Option<u8> {
+        None
+    }
+
+    // /RESET on the expansion port
+    fn reset(&mut self) {}
+}
+
+pub struct IoController {
+    ula: Ula,
+    // None unless a Kempston interface is plugged in
+    kempston: Option<Kempston>,
+    devices: Vec<Box<dyn IoDevice>>,
+}
+
+impl IoController {
+    pub fn new() -> Self {
+        Self {
+            ula: Ula::new(),
+            kempston: None,
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn ula(&self) -> &Ula {
+        &self.ula
+    }
+
+    pub fn ula_mut(&mut self) -> &mut Ula {
+        &mut self.ula
+    }
+
+    pub fn kempston(&self) -> Option<&Kempston> {
+        self.kempston.as_ref()
+    }
+
+    pub fn kempston_mut(&mut self) -> Option<&mut Kempston> {
+        self.kempston.as_mut()
+    }
+
+    pub fn attach_kempston(&mut self) {
+        self.kempston = Some(Kempston::new());
+    }
+
+    pub fn attach(&mut self, device: Box<dyn IoDevice>) {
+        self.devices.push(device);
+    }
+
+    // Return the ULA and joystick to their start-up state and reset every
+    // attached device
+    pub fn reset(&mut self) {
+        self.ula = Ula::new();
+        if let Some(kempston) = self.kempston.as_mut() {
+            *kempston = Kempston::new();
+        }
+        for device in self.devices.iter_mut() {
+            device.reset();
+        }
+    }
+}
+
+impl Default for IoController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoBus for IoController {
+    fn port_in(&mut self, port: u16) -> u8 {
+        // Several devices may respond to the same port, in which case their
+        // outputs are wired-AND together on the data bus
+        let mut val = 0xFF;
+
+        // The ULA decodes any even port (A0 low)
+        if port & 0x0001 == 0 {
+            val &= self.ula.read(port);
+        }
+
+        // Kempston interface only decodes A5 low
+        if let Some(kempston) = &self.kempston
+            && port & 0x0020 == 0
+        {
+            val &= kempston.read();
+        }
+
+        for device in self.devices.iter_mut() {
+            if let Some(byte) = device.read(port) {
+                val &= byte;
+            }
+        }
+
+        val
+    }
+
+    fn port_out(&mut self, port: u16, val: u8) {
+        if port & 0x0001 == 0 {
+            self.ula.write(val);
+        }
+
+        for device in self.devices.iter_mut() {
+            device.write(port, val);
+        }
+    }
+
+    // Nothing on a stock Spectrum drives the bus during an interrupt
+    // acknowledge, so it reads 0xFF unless a device pulls bits low
+    fn interrupt_data(&mut self) -> u8 {
+        self.devices
+            .iter_mut()
+            .filter_map(|device| device.interrupt_data())
+            .fold(0xFF, |val, byte| val & byte)
+    }
+}
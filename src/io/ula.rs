@@ -0,0 +1,79 @@
+// The ULA's port 0xFE: keyboard and EAR input, border/MIC/EAR output
+pub struct Ula {
+    // One byte per keyboard half-row, active low (bit clear = key pressed)
+    keyboard: [u8; 8],
+    border_colour: u8,
+    ear: bool,
+    mic: bool,
+}
+
+impl Ula {
+    pub fn new() -> Self {
+        Self {
+            keyboard: [0x1F; 8],
+            border_colour: 7,
+            ear: false,
+            mic: false,
+        }
+    }
+
+    pub fn read(&self, port: u16) -> u8 {
+        // Each address line A8-A15 held low selects a half-row.
+        // Selecting several rows at once ANDs their key bits together.
+        let high = (port >> 8) as u8;
+        let mut keys = 0x1F;
+        for (row, bits) in self.keyboard.iter().enumerate() {
+            if high & (1 << row) == 0 {
+                keys &= bits;
+            }
+        }
+
+        // Bits 5 and 7 are unused and read high. Bit 6 follows the EAR line,
+        // which on issue 3 boards echoes the last value written to bit 4.
+        0xA0 | (if self.ear { 0x40 } else { 0 }) | keys
+    }
+
+    pub fn write(&mut self, val: u8) {
+        // Bits 0-2: border colour
+        // Bit 3: MIC output
+        // Bit 4: EAR output (beeper)
+        self.border_colour = val & 0x07;
+        self.mic = (val & 0x08) != 0;
+        self.ear = (val & 0x10) != 0;
+    }
+
+    pub fn border_colour(&self) -> u8 {
+        self.border_colour
+    }
+
+    pub fn set_border_colour(&mut self, colour: u8) {
+        self.border_colour = colour & 0x07;
+    }
+
+    pub fn ear(&self) -> bool {
+        self.ear
+    }
+
+    pub fn mic(&self) -> bool {
+        self.mic
+    }
+
+    // Clear every key, ready for the host to report what's currently held down
+    pub fn release_all_keys(&mut self) {
+        self.keyboard = [0x1F; 8];
+    }
+
+    pub fn set_key(&mut self, row: usize, bit: u8, pressed: bool) {
+        if pressed {
+            self.keyboard[row] &= !(1 << bit);
+        } else {
+            self.keyboard[row] |= 1 << bit;
+        }
+    }
+}
+
+impl Default for Ula {
+    fn default() -> Self {
+        Self::new()
+    }
+}
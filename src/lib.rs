@@ -1,5 +1,7 @@
 pub mod cpu;
 pub mod emulator;
+pub mod io;
 pub mod memory;
+pub mod trace;
 pub mod video;
 pub use emulator::Emulator;
@@ -1,19 +1,34 @@
 use std::env;
+use std::fs;
 use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use zx_spectrum_emulator::cpu::Cpu;
-use zx_spectrum_emulator::memory::load_rom;
 use zx_spectrum_emulator::Emulator;
+use zx_spectrum_emulator::cpu::CpuVariant;
+use zx_spectrum_emulator::cpu::disasm::disassemble;
+use zx_spectrum_emulator::emulator::{EmulatorEvent, PowerOnState, UnknownOpcodePolicy};
+use zx_spectrum_emulator::memory::load_rom;
+use zx_spectrum_emulator::trace::Tracer;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     // We need a minimum of 2 args
     if args.len() < 2 {
-        eprintln!("Usage: {} <rom_file> [--debug]", args[0]);
+        eprintln!(
+            "Usage: {} <rom_file> [--debug] [--cpu zilog|nec|cmos] [--trace <file>] \
+             [--unknown-opcodes nop|stop|break] [--power-on ones|random[:seed]] [--kempston]",
+            args[0]
+        );
+        eprintln!("       {} disasm <file> [start_addr] [count]", args[0]);
         process::exit(1);
     }
 
+    if args[1] == "disasm" {
+        disasm(&args);
+        return;
+    }
+
     // Check if debug is enabled
     let debug_enabled: bool = args.contains(&"--debug".to_string());
 
@@ -23,8 +38,62 @@ fn main() {
         println!("Debug mode disabled...");
     }
 
-    // Remove --debug from args if it exists
-    let args: Vec<String> = args.into_iter().filter(|arg| arg != "--debug").collect();
+    // --trace <file> keeps the last TRACE_LENGTH instructions, written to
+    // the file on F1, on exit and when breaking on an unknown opcode
+    const TRACE_LENGTH: usize = 10000;
+    let mut args = args;
+    let trace_path = take_option(&mut args, "--trace");
+
+    // --unknown-opcodes nop|stop|break
+    let policy = match take_option(&mut args, "--unknown-opcodes").as_deref() {
+        None | Some("nop") => UnknownOpcodePolicy::Nop,
+        Some("stop") => UnknownOpcodePolicy::Stop,
+        Some("break") => UnknownOpcodePolicy::Break,
+        Some(other) => {
+            eprintln!(
+                "Error: Unknown opcode policy must be nop, stop or break, not {}",
+                other
+            );
+            process::exit(1);
+        }
+    };
+
+    // --cpu zilog|nec|cmos picks which chip's undocumented behaviour to follow
+    let variant = match take_option(&mut args, "--cpu").as_deref() {
+        None | Some("zilog") => CpuVariant::ZilogNmos,
+        Some("nec") => CpuVariant::NecNmos,
+        Some("cmos") => CpuVariant::Cmos,
+        Some(other) => {
+            eprintln!("Error: CPU must be zilog, nec or cmos, not {}", other);
+            process::exit(1);
+        }
+    };
+
+    // --power-on ones|random[:seed] sets what RAM and the registers hold at
+    // switch-on. Plain random takes its seed from the clock.
+    let power_on_state = match take_option(&mut args, "--power-on").as_deref() {
+        None | Some("ones") => PowerOnState::Ones,
+        Some("random") => PowerOnState::Random(clock_seed()),
+        Some(other) => match other.strip_prefix("random:").map(str::parse) {
+            Some(Ok(seed)) => PowerOnState::Random(seed),
+            _ => {
+                eprintln!(
+                    "Error: Power-on state must be ones, random or random:<seed>, not {}",
+                    other
+                );
+                process::exit(1);
+            }
+        },
+    };
+
+    // --kempston plugs in a Kempston joystick interface on port 0x1F
+    let kempston = args.contains(&"--kempston".to_string());
+
+    // Remove --debug and --kempston from args if they exist
+    let args: Vec<String> = args
+        .into_iter()
+        .filter(|arg| arg != "--debug" && arg != "--kempston")
+        .collect();
     // Load ROM file from args[1]
     let rom = match load_rom(&args[1]) {
         Ok(data) => {
@@ -37,7 +106,7 @@ fn main() {
         }
     };
 
-    let mut emulator = match Emulator::new(rom, debug_enabled) {
+    let mut emulator = match Emulator::new(rom, variant, debug_enabled) {
         Ok(emu) => emu,
         Err(e) => {
             eprintln!("Failed to create emulator: {}", e);
@@ -45,6 +114,27 @@ fn main() {
         }
     };
 
+    emulator.set_unknown_opcode_policy(policy);
+    emulator.power_on(power_on_state);
+    println!("Power-on state: {:?}", power_on_state);
+    if kempston {
+        emulator.io_mut().attach_kempston();
+    }
+
+    if let Some(path) = &trace_path {
+        match Tracer::to_file(path) {
+            Ok(mut tracer) => {
+                tracer.set_ring_buffer(TRACE_LENGTH);
+                emulator.set_tracer(tracer);
+                println!("Tracing the last {} instructions to {}", TRACE_LENGTH, path);
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to create {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+
     println!("Starting emulation...\n");
 
     // ZX Spectrum 48K timing
@@ -54,22 +144,35 @@ fn main() {
     let mut total_cycles = 0u64;
     let mut frame_count = 0u32;
     let mut _frames_since_init = 0u32;
+    let mut nmi_held = false;
+    // Set when breaking on an unknown opcode, until F3 is pressed
+    let mut paused = false;
 
     // Initialise screen to white paper, black ink
     emulator.clear_screen(0, 7, false);
 
-    while emulator.is_window_open() {
+    'running: while emulator.is_window_open() {
         let target_cycles = total_cycles + CYCLES_PER_FRAME;
         let mut frame_instruction_count = 0;
 
-        while total_cycles < target_cycles {
-            let cycles = emulator.step();
-            total_cycles += cycles as u64;
+        while !paused && total_cycles < target_cycles {
+            let before = emulator.total_cycles();
+            let result = emulator.step();
+            total_cycles += emulator.total_cycles() - before;
             frame_instruction_count += 1;
 
-            if emulator.is_halted() {
-                println!("CPU halted at PC: 0x{:04X}", emulator.cpu().pc);
-                break;
+            match result {
+                Ok(_) => {}
+                Err(event @ EmulatorEvent::Break(_)) => {
+                    println!("{} - paused, press F3 to continue", event);
+                    emulator.dump_system_info();
+                    paused = true;
+                }
+                Err(event) => {
+                    eprintln!("Error: {}", event);
+                    emulator.dump_system_info();
+                    break 'running;
+                }
             }
 
             // Safety check to prevent infinite loops
@@ -83,7 +186,7 @@ fn main() {
 
         // Wait for init period
         if frame_count < INIT_FRAMES {
-            if frame_count % 10 == 0 {
+            if frame_count.is_multiple_of(10) {
                 println!("Initialising... frame {}/{}", frame_count, INIT_FRAMES);
             }
 
@@ -109,42 +212,40 @@ fn main() {
             // Debug key shortcuts
             if keys.contains(&minifb::Key::F1) {
                 emulator.dump_system_info();
+                emulator.dump_trace("F1 pressed");
+            }
+
+            // NMI button - only fire once per press
+            let nmi_pressed = keys.contains(&minifb::Key::F2);
+            if nmi_pressed && !nmi_held {
+                println!("Triggering NMI...");
+                emulator.trigger_nmi();
+            }
+            nmi_held = nmi_pressed;
+
+            // Continue after a break
+            if paused && keys.contains(&minifb::Key::F3) {
+                println!("Continuing...");
+                paused = false;
             }
 
             // Reset emulator
             if keys.contains(&minifb::Key::F5) {
                 println!("Resetting emulator...");
-                *emulator.cpu_mut() = Cpu::new();
-                emulator.clear_screen(0, 7, false);
+                emulator.reset();
                 total_cycles = 0;
                 frame_count = 0;
                 _frames_since_init = 0;
             }
 
-            // Border colour cycling (for testing)
-            if keys.contains(&minifb::Key::Key1) {
-                emulator.set_border_colour(0); // Black
-            }
-            if keys.contains(&minifb::Key::Key2) {
-                emulator.set_border_colour(1); // Blue
-            }
-            if keys.contains(&minifb::Key::Key3) {
-                emulator.set_border_colour(2); // Red
-            }
-            if keys.contains(&minifb::Key::Key4) {
-                emulator.set_border_colour(3); // Magenta
-            }
-            if keys.contains(&minifb::Key::Key5) {
-                emulator.set_border_colour(4); // Green
-            }
-            if keys.contains(&minifb::Key::Key6) {
-                emulator.set_border_colour(5); // Cyan
-            }
-            if keys.contains(&minifb::Key::Key7) {
-                emulator.set_border_colour(6); // Yellow
-            }
-            if keys.contains(&minifb::Key::Key8) {
-                emulator.set_border_colour(7); // White
+            // Switch off and on again, with RAM and registers back to the
+            // power-on state
+            if keys.contains(&minifb::Key::F6) {
+                println!("Power cycling emulator...");
+                emulator.power_on(power_on_state);
+                total_cycles = 0;
+                frame_count = 0;
+                _frames_since_init = 0;
             }
 
             // Render display
@@ -157,6 +258,7 @@ fn main() {
         std::thread::sleep(std::time::Duration::from_millis(20));
     }
 
+    emulator.dump_trace("emulation stopped");
     println!("\nEmulation stopped.");
     println!("Total frames: {}", frame_count);
     println!("Total cycles: {}", total_cycles);
@@ -169,3 +271,61 @@ fn main() {
         }
     );
 }
+
+// A seed for --power-on random that differs from run to run
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(1)
+}
+
+// Remove a command line option and the value after it, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == name)?;
+    args.remove(i);
+    (i < args.len()).then(|| args.remove(i))
+}
+
+// List a ROM or memory image loaded at address 0, without running it.
+// start_addr is hex and defaults to 0; count defaults to 32 instructions.
+fn disasm(args: &[String]) {
+    if args.len() < 3 {
+        eprintln!("Usage: {} disasm <file> [start_addr] [count]", args[0]);
+        process::exit(1);
+    }
+
+    let data = fs::read(&args[2]).unwrap_or_else(|e| {
+        eprintln!("Error: Failed to read {}: {}", args[2], e);
+        process::exit(1);
+    });
+
+    let start = match args.get(3) {
+        Some(arg) => u16::from_str_radix(arg.trim_start_matches("0x"), 16).unwrap_or_else(|_| {
+            eprintln!("Error: Invalid start address: {}", arg);
+            process::exit(1);
+        }),
+        None => 0,
+    };
+
+    let count = match args.get(4) {
+        Some(arg) => arg.parse::<u32>().unwrap_or_else(|_| {
+            eprintln!("Error: Invalid count: {}", arg);
+            process::exit(1);
+        }),
+        None => 32,
+    };
+
+    // Anything past the end of the file reads as zero
+    let read = |addr: u16| data.get(addr as usize).copied().unwrap_or(0);
+
+    let mut addr = start;
+    for _ in 0..count {
+        let instruction = disassemble(addr, read);
+        let bytes: Vec<String> = (0..instruction.length)
+            .map(|i| format!("{:02X}", read(addr.wrapping_add(i))))
+            .collect();
+        println!("{:04X}  {:<12} {}", addr, bytes.join(" "), instruction.text);
+        addr = addr.wrapping_add(instruction.length);
+    }
+}
@@ -1,7 +1,29 @@
 mod ram;
 mod rom;
+pub use ram::FlatRam;
 pub use rom::load_rom;
 
+// Anything the CPU can fetch from and store to: the Spectrum's memory map,
+// or a flat 64K RAM for running CP/M test programs
+pub trait MemoryBus {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+
+    fn read_word(&self, addr: u16) -> u16 {
+        let lo = self.read(addr) as u16;
+        let hi = self.read(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn write_word(&mut self, addr: u16, val: u16) {
+        let hi = (val >> 8) as u8;
+        let lo = val as u8;
+
+        self.write(addr, lo);
+        self.write(addr.wrapping_add(1), hi);
+    }
+}
+
 pub struct Memory {
     rom: Vec<u8>,
     ram: Vec<u8>,
@@ -15,42 +37,14 @@ impl Memory {
         }
     }
 
-    pub fn rom(&self) -> &[u8] {
-        &self.rom
+    // RAM comes up holding whatever its cells settle to, taken from fill.
+    // The ROM's start-up routine tests and clears it.
+    pub fn power_on(&mut self, mut fill: impl FnMut() -> u8) {
+        self.ram.iter_mut().for_each(|byte| *byte = fill());
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x3FFF => self.rom[addr as usize],
-            0x4000..=0xFFFF => {
-                let offset = (addr - 0x4000) as usize;
-                self.ram[offset]
-            }
-        }
-    }
-
-    pub fn read_word(&self, addr: u16) -> u16 {
-        let lo = self.read(addr) as u16;
-        let hi = self.read(addr.wrapping_add(1)) as u16;
-        (hi << 8) | lo
-    }
-
-    pub fn write(&mut self, addr: u16, val: u8) {
-        match addr {
-            0x0000..=0x3FFF => {} // ROM is not writable
-            0x4000..=0xFFFF => {
-                let offset = (addr - 0x4000) as usize;
-                self.ram[offset] = val;
-            }
-        }
-    }
-
-    pub fn write_word(&mut self, addr: u16, val: u16) {
-        let hi = (val >> 8) as u8;
-        let lo = val as u8;
-
-        self.write(addr, lo);
-        self.write(addr.wrapping_add(1), hi);
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
     }
 
     pub fn screen_bitmap(&self) -> &[u8] {
@@ -78,3 +72,25 @@ impl Memory {
         }
     }
 }
+
+impl MemoryBus for Memory {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom[addr as usize],
+            0x4000..=0xFFFF => {
+                let offset = (addr - 0x4000) as usize;
+                self.ram[offset]
+            }
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x3FFF => {} // ROM is not writable
+            0x4000..=0xFFFF => {
+                let offset = (addr - 0x4000) as usize;
+                self.ram[offset] = val;
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use super::MemoryBus;
+
+// 64KB of plain RAM with no ROM or paging, for running CP/M programs such
+// as ZEXDOC/ZEXALL directly on the CPU
+pub struct FlatRam {
+    data: Vec<u8>,
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0; 0x10000],
+        }
+    }
+
+    // Copy bytes in starting at addr, wrapping at the top of memory
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.data[addr.wrapping_add(i as u16) as usize] = byte;
+        }
+    }
+}
+
+impl MemoryBus for FlatRam {
+    fn read(&self, addr: u16) -> u8 {
+        self.data[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.data[addr as usize] = val;
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::cpu::disasm::disassemble;
+use crate::cpu::{Cpu, StepKind};
+use crate::memory::{Memory, MemoryBus};
+
+// Instruction trace logger. Each traced instruction is written as one line
+// with its address, disassembly, the registers and flags before it runs and
+// the T-state counter, e.g.
+//
+//   T=0000012345 PC=11DC LD (HL),$02         AF=0044 BC=... F=-Z-----
+//
+// An accepted interrupt gets a line of its own, "NMI" or "INT (IM n)" at the
+// address it interrupted.
+//
+// By default every line goes straight to the writer. In ring-buffer mode
+// only the last N lines are kept, and they are written out when a
+// breakpoint is reached or dump() is called.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    ranges: Vec<RangeInclusive<u16>>,
+    breakpoints: Vec<u16>,
+    ring: Option<VecDeque<String>>,
+    ring_size: usize,
+}
+
+impl Tracer {
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        Self {
+            writer,
+            ranges: Vec::new(),
+            breakpoints: Vec::new(),
+            ring: None,
+            ring_size: 0,
+        }
+    }
+
+    pub fn to_file(path: &str) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file))))
+    }
+
+    // Only trace instructions whose address falls in one of the ranges
+    // added. With no ranges, everything is traced.
+    pub fn add_range(&mut self, range: RangeInclusive<u16>) {
+        self.ranges.push(range);
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.push(addr);
+    }
+
+    // Keep only the last `size` lines until a breakpoint or dump()
+    pub fn set_ring_buffer(&mut self, size: usize) {
+        self.ring = Some(VecDeque::with_capacity(size));
+        self.ring_size = size;
+    }
+
+    // Log a step the CPU has just run, given its state before the step, the
+    // instruction it was about to run (from next_instruction()) and the
+    // T-state counter when it started
+    pub fn trace(
+        &mut self,
+        cpu: &Cpu,
+        step: StepKind,
+        instruction: &str,
+        tstates: u64,
+    ) -> io::Result<()> {
+        let (pc, text) = match step {
+            StepKind::Nmi => (cpu.pc, "NMI".to_string()),
+            StepKind::Interrupt => (cpu.pc, format!("INT (IM {})", cpu.interrupt_mode)),
+            // The prefix before the opcode was fetched by the previous step
+            StepKind::Prefix => (cpu.pc.wrapping_sub(1), instruction.to_string()),
+            StepKind::Instruction | StepKind::Halted => (cpu.pc, instruction.to_string()),
+        };
+
+        if self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc)) {
+            let line = trace_line(cpu, pc, &text, tstates);
+            match &mut self.ring {
+                Some(ring) => {
+                    if ring.len() == self.ring_size {
+                        ring.pop_front();
+                    }
+                    ring.push_back(line);
+                }
+                None => writeln!(self.writer, "{}", line)?,
+            }
+        }
+
+        let interrupted = matches!(step, StepKind::Nmi | StepKind::Interrupt);
+        if !interrupted && self.breakpoints.contains(&pc) {
+            self.dump(&format!("breakpoint at ${:04X}", pc))?;
+        }
+        Ok(())
+    }
+
+    // Write out the ring buffer, if any, followed by a marker line saying
+    // why. Does nothing but write the marker when streaming.
+    pub fn dump(&mut self, reason: &str) -> io::Result<()> {
+        if let Some(ring) = &mut self.ring {
+            for line in ring.drain(..) {
+                writeln!(self.writer, "{}", line)?;
+            }
+        }
+        writeln!(self.writer, "--- {} ---", reason)?;
+        self.writer.flush()
+    }
+}
+
+// Disassemble the instruction the CPU will run next. This has to be done
+// before the step, as the instruction may overwrite itself.
+pub fn next_instruction(cpu: &Cpu, memory: &Memory) -> String {
+    // After a prefix that was a step of its own, the next one was already
+    // fetched
+    let pc = match cpu.pending_prefix {
+        Some(_) => cpu.pc.wrapping_sub(1),
+        None => cpu.pc,
+    };
+    disassemble(pc, |addr| memory.read(addr)).text
+}
+
+fn trace_line(cpu: &Cpu, pc: u16, text: &str, tstates: u64) -> String {
+    format!(
+        "T={:010} PC={:04X} {:<20} AF={:04X} BC={:04X} DE={:04X} HL={:04X} \
+         IX={:04X} IY={:04X} SP={:04X} F={}",
+        tstates,
+        pc,
+        text,
+        cpu.af(),
+        cpu.bc(),
+        cpu.de(),
+        cpu.hl(),
+        cpu.ix,
+        cpu.iy,
+        cpu.sp,
+        flag_string(cpu.f),
+    )
+}
+
+// F as SZ5H3PNC, with - for each flag that's clear
+fn flag_string(f: u8) -> String {
+    "SZ5H3PNC"
+        .chars()
+        .enumerate()
+        .map(|(i, name)| if f & (0x80 >> i) != 0 { name } else { '-' })
+        .collect()
+}
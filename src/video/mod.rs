@@ -1,5 +1,6 @@
 use crate::cpu::Cpu;
-use crate::memory::Memory;
+use crate::cpu::disasm::disassemble;
+use crate::memory::{Memory, MemoryBus};
 use minifb::{Window, WindowOptions};
 
 const SPECTRUM_SCREEN_WIDTH: usize = 256;
@@ -255,17 +256,22 @@ impl Video {
         // Index Registers
         self.draw_text("INDEX REGS:", x_offset, y_pos, colour);
         y_pos += 12 * FONT_SCALE;
-        self.draw_text(&format!("IX: {:04X}", cpu.ix), x_offset, y_pos, colour);
+        self.draw_text(
+            &format!("IX: {:04X}  IY: {:04X}", cpu.ix, cpu.iy),
+            x_offset,
+            y_pos,
+            colour,
+        );
         y_pos += 10 * FONT_SCALE;
-        self.draw_text(&format!("IY: {:04X}", cpu.iy), x_offset, y_pos, colour);
+        self.draw_text(&format!("WZ: {:04X}", cpu.wz), x_offset, y_pos, colour);
         y_pos += 15 * FONT_SCALE;
 
         // Current Instruction
-        self.draw_text("CURRENT OPCODE:", x_offset, y_pos, colour);
+        self.draw_text("CURRENT INSTRUCTION:", x_offset, y_pos, colour);
         y_pos += 12 * FONT_SCALE;
-        let opcode = memory.read(cpu.pc);
+        let instruction = disassemble(cpu.pc, |addr| memory.read(addr));
         self.draw_text(
-            &format!("[{:04X}]: {:02X}", cpu.pc, opcode),
+            &format!("[{:04X}]: {}", cpu.pc, instruction.text),
             x_offset,
             y_pos,
             colour,
@@ -331,9 +337,9 @@ impl Video {
     fn draw_char(&mut self, ch: char, x: usize, y: usize, colour: u32) {
         let glyph = get_font_glyph(ch);
 
-        for row in 0..FONT_HEIGHT {
+        for (row, line) in glyph.iter().enumerate().take(FONT_HEIGHT) {
             for col in 0..FONT_WIDTH {
-                if (glyph[row] >> (4 - col)) & 1 != 0 {
+                if (line >> (4 - col)) & 1 != 0 {
                     for sy in 0..FONT_SCALE {
                         for sx in 0..FONT_SCALE {
                             let px = x + (col * FONT_SCALE) + sx;
@@ -375,6 +381,10 @@ fn get_font_glyph(ch: char) -> [u8; 7] {
         ')' => [0x00, 0x08, 0x04, 0x04, 0x04, 0x08, 0x00],
         '[' => [0x00, 0x0E, 0x08, 0x08, 0x08, 0x0E, 0x00],
         ']' => [0x00, 0x0E, 0x02, 0x02, 0x02, 0x0E, 0x00],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '$' => [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
         '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
         '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
         '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
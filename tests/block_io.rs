@@ -0,0 +1,83 @@
+// INI/IND/OUTI/OUTD and their repeating forms: data moves between ports
+// and (HL), and the flags follow the byte transferred
+
+mod common;
+
+use common::{ORIGIN, TestBus};
+use zx_spectrum_emulator::cpu::Cpu;
+
+// Step until PC leaves the block instruction at ORIGIN
+fn run_block(cpu: &mut Cpu, bus: &mut TestBus) -> u32 {
+    let mut tstates = 0;
+    loop {
+        tstates += cpu.step(bus);
+        if cpu.pc != ORIGIN {
+            return tstates;
+        }
+    }
+}
+
+#[test]
+fn inir_reads_into_memory() {
+    let (mut cpu, mut bus) = common::setup(&[0xED, 0xB2]);
+    bus.reads.extend([0x11, 0x22, 0x33]);
+    cpu.set_bc(0x03FE);
+    cpu.set_hl(0x9000);
+
+    let tstates = run_block(&mut cpu, &mut bus);
+    assert_eq!(&bus.memory[0x9000..0x9003], [0x11, 0x22, 0x33]);
+    assert_eq!(cpu.hl(), 0x9003);
+    assert_eq!(cpu.b, 0);
+    assert_eq!(tstates, 21 + 21 + 16);
+    assert_eq!(cpu.f & 0x40, 0x40);
+}
+
+#[test]
+fn otdr_writes_from_memory() {
+    let (mut cpu, mut bus) = common::setup(&[0xED, 0xBB]);
+    bus.memory[0x9000..0x9003].copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+    cpu.set_bc(0x03FE);
+    cpu.set_hl(0x9002);
+
+    run_block(&mut cpu, &mut bus);
+    // B is decremented before the write, so it shows on the port's high byte
+    assert_eq!(bus.writes, [(0x02FE, 0xCC), (0x01FE, 0xBB), (0x00FE, 0xAA)]);
+    assert_eq!(cpu.hl(), 0x8FFF);
+    assert_eq!(cpu.b, 0);
+}
+
+#[test]
+fn ini_flags() {
+    // k = 0xFE + (C + 1) = 0x11E carries into H and C. N is bit 7 of the
+    // byte, P/V the parity of (k & 7) ^ B = 6 ^ 0x0A, and S, Z, 5 and 3
+    // come from B.
+    let (mut cpu, mut bus) = common::setup(&[0xED, 0xA2]);
+    bus.reads.extend([0xFE]);
+    cpu.set_bc(0x0B1F);
+    cpu.set_hl(0x9000);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.b, 0x0A);
+    assert_eq!(cpu.f, 0x08 | 0x10 | 0x04 | 0x02 | 0x01);
+
+    // IND adds C - 1 instead: 0x01 + 0x00 doesn't carry, and 1 ^ 0x80 has
+    // even parity
+    let (mut cpu, mut bus) = common::setup(&[0xED, 0xAA]);
+    bus.reads.extend([0x01]);
+    cpu.set_bc(0x8101);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.b, 0x80);
+    assert_eq!(cpu.f, 0x80 | 0x04);
+}
+
+#[test]
+fn outi_flags() {
+    // k = byte + L after the increment: 0x80 + 0x80 = 0x100. B reaches 0.
+    let (mut cpu, mut bus) = common::setup(&[0xED, 0xA3]);
+    bus.memory[0x907F] = 0x80;
+    cpu.set_bc(0x0100);
+    cpu.set_hl(0x907F);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.hl(), 0x9080);
+    assert_eq!(bus.writes, [(0x0000, 0x80)]);
+    assert_eq!(cpu.f, 0x40 | 0x10 | 0x04 | 0x02 | 0x01);
+}
@@ -0,0 +1,100 @@
+// The bus and CPU fixture shared by the instruction-level tests. Each test
+// crate uses a different part of it.
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+
+use zx_spectrum_emulator::cpu::{Bus, Cpu};
+
+pub const ORIGIN: u16 = 0x8000;
+pub const STACK_TOP: u16 = 0xFF00;
+
+// 64K of RAM. Port reads are played back from a queue, floating high once
+// it runs dry, and port writes are recorded. interrupt_data is what an
+// interrupt acknowledge reads from the data bus. ticks counts the T-states
+// the CPU has told the bus about.
+pub struct TestBus {
+    pub memory: Vec<u8>,
+    pub reads: VecDeque<u8>,
+    pub writes: Vec<(u16, u8)>,
+    pub interrupt_data: u8,
+    pub ticks: u64,
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+            reads: VecDeque::new(),
+            writes: Vec::new(),
+            interrupt_data: 0xFF,
+            ticks: 0,
+        }
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        let start = addr as usize;
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // Little-endian words, e.g. for vectors and the stack
+    pub fn read_word(&self, addr: u16) -> u16 {
+        let lo = self.memory[addr as usize] as u16;
+        let hi = self.memory[addr.wrapping_add(1) as usize] as u16;
+        (hi << 8) | lo
+    }
+
+    pub fn write_word(&mut self, addr: u16, val: u16) {
+        self.memory[addr as usize] = val as u8;
+        self.memory[addr.wrapping_add(1) as usize] = (val >> 8) as u8;
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+    }
+
+    fn port_in(&mut self, _port: u16) -> u8 {
+        self.reads.pop_front().unwrap_or(0xFF)
+    }
+
+    fn port_out(&mut self, port: u16, val: u8) {
+        self.writes.push((port, val));
+    }
+
+    fn interrupt_data(&mut self) -> u8 {
+        self.interrupt_data
+    }
+
+    fn tick(&mut self, tstates: u32) {
+        self.ticks += tstates as u64;
+    }
+}
+
+// A CPU about to run program from ORIGIN, with the stack at STACK_TOP
+pub fn setup(program: &[u8]) -> (Cpu, TestBus) {
+    let mut bus = TestBus::new();
+    bus.load(ORIGIN, program);
+    let mut cpu = Cpu::default();
+    cpu.pc = ORIGIN;
+    cpu.sp = STACK_TOP;
+    (cpu, bus)
+}
+
+// Run program from ORIGIN for the given number of steps, once prepare has
+// set up the CPU and bus. Returns them with the T-states the steps took.
+pub fn run(
+    program: &[u8],
+    prepare: impl FnOnce(&mut Cpu, &mut TestBus),
+    steps: usize,
+) -> (Cpu, TestBus, u32) {
+    let (mut cpu, mut bus) = setup(program);
+    prepare(&mut cpu, &mut bus);
+    let tstates = (0..steps).map(|_| cpu.step(&mut bus)).sum();
+    (cpu, bus, tstates)
+}
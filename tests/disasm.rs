@@ -0,0 +1,189 @@
+// Disassembler output and instruction lengths for each opcode table
+
+use zx_spectrum_emulator::cpu::disasm::disassemble;
+
+const ORIGIN: u16 = 0x8000;
+
+// Disassemble bytes placed at ORIGIN, with zeros after them
+fn disasm(bytes: &[u8]) -> (String, u16) {
+    let instruction = disassemble(ORIGIN, |addr| {
+        bytes
+            .get(addr.wrapping_sub(ORIGIN) as usize)
+            .copied()
+            .unwrap_or(0)
+    });
+    (instruction.text, instruction.length)
+}
+
+fn check(cases: &[(&[u8], &str, u16)]) {
+    for &(bytes, text, length) in cases {
+        assert_eq!(disasm(bytes), (text.to_string(), length), "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn main_table() {
+    check(&[
+        (&[0x00], "NOP", 1),
+        (&[0x08], "EX AF,AF'", 1),
+        (&[0x01, 0x34, 0x12], "LD BC,$1234", 3),
+        (&[0x09], "ADD HL,BC", 1),
+        (&[0x22, 0x00, 0x90], "LD ($9000),HL", 3),
+        (&[0x3A, 0xCD, 0xAB], "LD A,($ABCD)", 3),
+        (&[0x33], "INC SP", 1),
+        (&[0x35], "DEC (HL)", 1),
+        (&[0x36, 0x42], "LD (HL),$42", 2),
+        (&[0x27], "DAA", 1),
+        (&[0x76], "HALT", 1),
+        (&[0x41], "LD B,C", 1),
+        (&[0x7E], "LD A,(HL)", 1),
+        (&[0x96], "SUB (HL)", 1),
+        (&[0x8F], "ADC A,A", 1),
+        (&[0xC0], "RET NZ", 1),
+        (&[0xF1], "POP AF", 1),
+        (&[0xE9], "JP (HL)", 1),
+        (&[0xCA, 0x00, 0x40], "JP Z,$4000", 3),
+        (&[0xD3, 0xFE], "OUT ($FE),A", 2),
+        (&[0xDB, 0x1F], "IN A,($1F)", 2),
+        (&[0xE3], "EX (SP),HL", 1),
+        (&[0xEB], "EX DE,HL", 1),
+        (&[0xFC, 0x34, 0x12], "CALL M,$1234", 3),
+        (&[0xCD, 0x34, 0x12], "CALL $1234", 3),
+        (&[0xFE, 0x80], "CP $80", 2),
+        (&[0xFF], "RST $38", 1),
+    ]);
+}
+
+#[test]
+fn relative_jumps_show_their_target() {
+    check(&[
+        (&[0x18, 0x00], "JR $8002", 2),
+        (&[0x18, 0xFE], "JR $8000", 2),
+        (&[0x10, 0x10], "DJNZ $8012", 2),
+        (&[0x38, 0x80], "JR C,$7F82", 2),
+    ]);
+}
+
+#[test]
+fn cb_table() {
+    check(&[
+        (&[0xCB, 0x00], "RLC B", 2),
+        (&[0xCB, 0x0E], "RRC (HL)", 2),
+        (&[0xCB, 0x37], "SLL A", 2),
+        (&[0xCB, 0x7C], "BIT 7,H", 2),
+        (&[0xCB, 0x86], "RES 0,(HL)", 2),
+        (&[0xCB, 0xFF], "SET 7,A", 2),
+    ]);
+}
+
+#[test]
+fn ed_table() {
+    check(&[
+        (&[0xED, 0x70], "IN F,(C)", 2),
+        (&[0xED, 0x78], "IN A,(C)", 2),
+        (&[0xED, 0x71], "OUT (C),0", 2),
+        (&[0xED, 0x42], "SBC HL,BC", 2),
+        (&[0xED, 0x7A], "ADC HL,SP", 2),
+        (&[0xED, 0x43, 0x00, 0x90], "LD ($9000),BC", 4),
+        (&[0xED, 0x7B, 0x00, 0x90], "LD SP,($9000)", 4),
+        (&[0xED, 0x44], "NEG", 2),
+        (&[0xED, 0x4D], "RETI", 2),
+        (&[0xED, 0x45], "RETN", 2),
+        (&[0xED, 0x5E], "IM 2", 2),
+        (&[0xED, 0x4E], "IM 0", 2),
+        (&[0xED, 0x57], "LD A,I", 2),
+        (&[0xED, 0x6F], "RLD", 2),
+        (&[0xED, 0xB0], "LDIR", 2),
+        (&[0xED, 0xA2], "INI", 2),
+        (&[0xED, 0xBB], "OTDR", 2),
+    ]);
+}
+
+#[test]
+fn ed_nops() {
+    // Undefined ED opcodes are two-byte NOPs, including the holes in the
+    // 40-7F block and among the block instructions
+    for opcode in [0x00, 0x3F, 0x77, 0x7F, 0x80, 0xA4, 0xBC, 0xC0, 0xFF] {
+        check(&[(&[0xED, opcode], "NOP", 2)]);
+    }
+}
+
+#[test]
+fn index_table() {
+    check(&[
+        (&[0xDD, 0x21, 0x34, 0x12], "LD IX,$1234", 4),
+        (&[0xFD, 0x29], "ADD IY,IY", 2),
+        (&[0xDD, 0x19], "ADD IX,DE", 2),
+        (&[0xFD, 0x22, 0x00, 0x90], "LD ($9000),IY", 4),
+        (&[0xDD, 0x23], "INC IX", 2),
+        (&[0xDD, 0x7E, 0x05], "LD A,(IX+$05)", 3),
+        (&[0xFD, 0x70, 0xFE], "LD (IY-$02),B", 3),
+        (&[0xDD, 0x34, 0x80], "INC (IX-$80)", 3),
+        // The displacement comes before the immediate byte
+        (&[0xDD, 0x36, 0x05, 0x42], "LD (IX+$05),$42", 4),
+        (&[0xFD, 0x86, 0x7F], "ADD A,(IY+$7F)", 3),
+        (&[0xDD, 0xE1], "POP IX", 2),
+        (&[0xFD, 0xE9], "JP (IY)", 2),
+        (&[0xDD, 0xF9], "LD SP,IX", 2),
+        (&[0xFD, 0xE3], "EX (SP),IY", 2),
+        // Opcodes that don't use HL are unchanged, but the prefix counts
+        (&[0xDD, 0x00], "NOP", 2),
+        (&[0xFD, 0xEB], "EX DE,HL", 2),
+        (&[0xDD, 0x41], "LD B,C", 2),
+    ]);
+}
+
+#[test]
+fn index_register_halves() {
+    check(&[
+        (&[0xDD, 0x26, 0x12], "LD IXH,$12", 3),
+        (&[0xFD, 0x2C], "INC IYL", 2),
+        (&[0xDD, 0x65], "LD IXH,IXL", 2),
+        (&[0xFD, 0x7C], "LD A,IYH", 2),
+        (&[0xDD, 0x44], "LD B,IXH", 2),
+        (&[0xFD, 0xA5], "AND IYL", 2),
+        // With (IX+d) as the other operand H and L are the real registers
+        (&[0xDD, 0x66, 0x01], "LD H,(IX+$01)", 3),
+        (&[0xFD, 0x75, 0xFF], "LD (IY-$01),L", 3),
+    ]);
+}
+
+#[test]
+fn index_cb_table() {
+    check(&[
+        // The displacement comes before the opcode
+        (&[0xDD, 0xCB, 0x05, 0x06], "RLC (IX+$05)", 4),
+        (&[0xFD, 0xCB, 0xFE, 0x46], "BIT 0,(IY-$02)", 4),
+        (&[0xDD, 0xCB, 0x10, 0x7E], "BIT 7,(IX+$10)", 4),
+        (&[0xFD, 0xCB, 0x00, 0x8E], "RES 1,(IY+$00)", 4),
+        (&[0xDD, 0xCB, 0x80, 0xFE], "SET 7,(IX-$80)", 4),
+        // Other register fields also copy the result to that register
+        (&[0xDD, 0xCB, 0x05, 0x00], "RLC (IX+$05),B", 4),
+        (&[0xFD, 0xCB, 0x01, 0x3F], "SRL (IY+$01),A", 4),
+        (&[0xDD, 0xCB, 0xFF, 0x95], "RES 2,(IX-$01),L", 4),
+        (&[0xFD, 0xCB, 0x02, 0xC4], "SET 0,(IY+$02),H", 4),
+        // BIT only reads, whatever the register field
+        (&[0xDD, 0xCB, 0x03, 0x41], "BIT 0,(IX+$03)", 4),
+    ]);
+}
+
+#[test]
+fn prefix_followed_by_prefix() {
+    // The first prefix is a NOP on its own; the next one starts a new
+    // instruction
+    check(&[
+        (&[0xDD, 0xDD, 0x21, 0x34, 0x12], "NOP", 1),
+        (&[0xDD, 0xFD, 0x21, 0x34, 0x12], "NOP", 1),
+        (&[0xFD, 0xDD, 0x21, 0x34, 0x12], "NOP", 1),
+    ]);
+}
+
+#[test]
+fn index_prefix_before_ed() {
+    // The prefix is ignored, but runs with the ED instruction as one step
+    check(&[
+        (&[0xDD, 0xED, 0x44], "NEG", 3),
+        (&[0xFD, 0xED, 0xB0], "LDIR", 3),
+        (&[0xDD, 0xED, 0x43, 0x00, 0x90], "LD ($9000),BC", 5),
+    ]);
+}
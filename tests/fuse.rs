@@ -0,0 +1,403 @@
+// Replays the FUSE emulator's core tests (z80/tests/tests.in and
+// tests.expected) against the CPU. Each test gives the registers and memory
+// before, and the expected registers, changed memory, T-state count and the
+// timeline of bus events the instruction produces.
+//
+// The files are part of FUSE (GPL) and aren't shipped with the crate. Point
+// FUSE_TESTS_DIR at the directory holding them:
+//
+//     FUSE_TESTS_DIR=/path/to/fuse/z80/tests cargo test --test fuse -- --ignored
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use zx_spectrum_emulator::cpu::{Bus, Cpu, Cycle};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Event {
+    time: Option<u32>,
+    kind: String,
+    addr: u16,
+    data: Option<u8>,
+}
+
+impl Event {
+    fn describe(&self) -> String {
+        let time = self
+            .time
+            .map_or("    ?".to_string(), |t| format!("{:5}", t));
+        match self.data {
+            Some(data) => format!("{} {} {:04X} {:02X}", time, self.kind, self.addr, data),
+            None => format!("{} {} {:04X}", time, self.kind, self.addr),
+        }
+    }
+
+    // PC events come from FUSE's port contention model, not the CPU
+    fn is_cpu_event(&self) -> bool {
+        self.kind != "PC"
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Registers {
+    af: u16,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    af_: u16,
+    bc_: u16,
+    de_: u16,
+    hl_: u16,
+    ix: u16,
+    iy: u16,
+    sp: u16,
+    pc: u16,
+    memptr: u16,
+    i: u8,
+    r: u8,
+    iff1: bool,
+    iff2: bool,
+    im: u8,
+    halted: bool,
+    tstates: u32,
+}
+
+struct TestInput {
+    name: String,
+    regs: Registers,
+    memory: Vec<(u16, Vec<u8>)>,
+}
+
+struct TestExpected {
+    events: Vec<Event>,
+    regs: Registers,
+    memory: Vec<(u16, Vec<u8>)>,
+}
+
+// FUSE's test machine: 64K of RAM filled with DE AD BE EF, port reads
+// return the high byte of the port, and every access is logged. Memory
+// and internal cycles log MC as they start, with MR/MW once they end.
+struct TestBus {
+    memory: Vec<u8>,
+    events: Vec<Event>,
+    time: u32,
+}
+
+impl TestBus {
+    fn log(&mut self, kind: &str, addr: u16, data: Option<u8>) {
+        self.events.push(Event {
+            time: Some(self.time),
+            kind: kind.to_string(),
+            addr,
+            data,
+        });
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.memory[addr as usize];
+        self.log("MR", addr, Some(data));
+        data
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize] = val;
+        self.log("MW", addr, Some(val));
+    }
+
+    fn port_in(&mut self, port: u16) -> u8 {
+        let data = (port >> 8) as u8;
+        self.log("PR", port, Some(data));
+        data
+    }
+
+    fn port_out(&mut self, port: u16, val: u8) {
+        self.log("PW", port, Some(val));
+    }
+
+    fn start_cycle(&mut self, cycle: Cycle, addr: u16) -> u32 {
+        if !matches!(cycle, Cycle::PortRead | Cycle::PortWrite) {
+            self.log("MC", addr, None);
+        }
+        0
+    }
+
+    fn tick(&mut self, tstates: u32) {
+        self.time += tstates;
+    }
+}
+
+fn hex16(s: &str) -> u16 {
+    u16::from_str_radix(s, 16).unwrap_or_else(|_| panic!("Bad hex word {:?}", s))
+}
+
+fn hex8(s: &str) -> u8 {
+    u8::from_str_radix(s, 16).unwrap_or_else(|_| panic!("Bad hex byte {:?}", s))
+}
+
+// The two register lines shared by both files:
+//   AF BC DE HL AF' BC' DE' HL' IX IY SP PC MEMPTR
+//   I R IFF1 IFF2 IM halted tstates
+fn parse_registers(line1: &str, line2: &str) -> Registers {
+    let w: Vec<u16> = line1.split_whitespace().map(hex16).collect();
+    let s: Vec<&str> = line2.split_whitespace().collect();
+    assert!(w.len() == 13 && s.len() == 7, "Bad register lines");
+
+    Registers {
+        af: w[0],
+        bc: w[1],
+        de: w[2],
+        hl: w[3],
+        af_: w[4],
+        bc_: w[5],
+        de_: w[6],
+        hl_: w[7],
+        ix: w[8],
+        iy: w[9],
+        sp: w[10],
+        pc: w[11],
+        memptr: w[12],
+        i: hex8(s[0]),
+        r: hex8(s[1]),
+        iff1: s[2] != "0",
+        iff2: s[3] != "0",
+        im: s[4].parse().expect("Bad IM"),
+        halted: s[5] != "0",
+        tstates: s[6].parse().expect("Bad T-state count"),
+    }
+}
+
+// "addr byte byte ... -1"
+fn parse_memory_line(line: &str) -> (u16, Vec<u8>) {
+    let mut fields = line.split_whitespace();
+    let addr = hex16(fields.next().expect("Missing address"));
+    let bytes = fields.take_while(|f| *f != "-1").map(hex8).collect();
+    (addr, bytes)
+}
+
+fn parse_inputs(text: &str) -> Vec<TestInput> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let mut tests = Vec::new();
+
+    while let Some(name) = lines.next() {
+        let regs = parse_registers(lines.next().unwrap(), lines.next().unwrap());
+        let mut memory = Vec::new();
+        for line in lines.by_ref() {
+            if line.trim() == "-1" {
+                break;
+            }
+            memory.push(parse_memory_line(line));
+        }
+        tests.push(TestInput {
+            name: name.trim().to_string(),
+            regs,
+            memory,
+        });
+    }
+
+    tests
+}
+
+fn parse_expected(text: &str) -> Vec<(String, TestExpected)> {
+    let mut tests = Vec::new();
+
+    // Tests are separated by blank lines
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().filter(|l| !l.trim().is_empty()).peekable();
+        let Some(name) = lines.next() else {
+            continue;
+        };
+
+        // Event lines are indented; the register lines aren't
+        let mut events = Vec::new();
+        while let Some(line) = lines.next_if(|l| l.starts_with(char::is_whitespace)) {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            events.push(Event {
+                time: Some(f[0].parse().expect("Bad event time")),
+                kind: f[1].to_string(),
+                addr: hex16(f[2]),
+                data: f.get(3).map(|d| hex8(d)),
+            });
+        }
+
+        let regs = parse_registers(lines.next().unwrap(), lines.next().unwrap());
+        let memory = lines.map(parse_memory_line).collect();
+        tests.push((
+            name.trim().to_string(),
+            TestExpected {
+                events,
+                regs,
+                memory,
+            },
+        ));
+    }
+
+    tests
+}
+
+fn load(cpu: &mut Cpu, regs: &Registers) {
+    cpu.set_af(regs.af);
+    cpu.set_bc(regs.bc);
+    cpu.set_de(regs.de);
+    cpu.set_hl(regs.hl);
+    cpu.af_shadow = regs.af_;
+    cpu.bc_shadow = regs.bc_;
+    cpu.de_shadow = regs.de_;
+    cpu.hl_shadow = regs.hl_;
+    cpu.ix = regs.ix;
+    cpu.iy = regs.iy;
+    cpu.sp = regs.sp;
+    cpu.pc = regs.pc;
+    cpu.wz = regs.memptr;
+    cpu.i = regs.i;
+    cpu.r = regs.r;
+    cpu.iff1 = regs.iff1;
+    cpu.iff2 = regs.iff2;
+    cpu.interrupt_mode = regs.im;
+    cpu.is_halted = regs.halted;
+}
+
+fn save(cpu: &Cpu, tstates: u32) -> Registers {
+    Registers {
+        af: cpu.af(),
+        bc: cpu.bc(),
+        de: cpu.de(),
+        hl: cpu.hl(),
+        af_: cpu.af_shadow,
+        bc_: cpu.bc_shadow,
+        de_: cpu.de_shadow,
+        hl_: cpu.hl_shadow,
+        ix: cpu.ix,
+        iy: cpu.iy,
+        sp: cpu.sp,
+        pc: cpu.pc,
+        memptr: cpu.wz,
+        i: cpu.i,
+        r: cpu.r,
+        iff1: cpu.iff1,
+        iff2: cpu.iff2,
+        im: cpu.interrupt_mode,
+        halted: cpu.is_halted,
+        tstates,
+    }
+}
+
+// Run one test and describe every difference from the expected results
+fn run_test(input: &TestInput, expected: &TestExpected) -> String {
+    let mut bus = TestBus {
+        memory: [0xDE, 0xAD, 0xBE, 0xEF].repeat(0x4000),
+        events: Vec::new(),
+        time: 0,
+    };
+    for (addr, bytes) in &input.memory {
+        for (i, &byte) in bytes.iter().enumerate() {
+            bus.memory[addr.wrapping_add(i as u16) as usize] = byte;
+        }
+    }
+
+    let mut cpu = Cpu::default();
+    load(&mut cpu, &input.regs);
+
+    // Like FUSE, keep executing whole instructions until the test's
+    // T-state budget is used up
+    let mut tstates = 0u32;
+    while tstates < input.regs.tstates {
+        tstates += cpu.step(&mut bus);
+    }
+
+    let mut diffs = String::new();
+
+    let actual = save(&cpu, tstates);
+    if actual != expected.regs {
+        let _ = writeln!(diffs, "  expected {:04X?}", expected.regs);
+        let _ = writeln!(diffs, "  got      {:04X?}", actual);
+    }
+
+    for (addr, bytes) in &expected.memory {
+        for (i, &byte) in bytes.iter().enumerate() {
+            let at = addr.wrapping_add(i as u16);
+            let got = bus.memory[at as usize];
+            if got != byte {
+                let _ = writeln!(
+                    diffs,
+                    "  ({:04X}): expected {:02X}, got {:02X}",
+                    at, byte, got
+                );
+            }
+        }
+    }
+
+    let expected_events: Vec<&Event> = expected
+        .events
+        .iter()
+        .filter(|e| e.is_cpu_event())
+        .collect();
+    let actual_events = &bus.events;
+    let same = expected_events.len() == actual_events.len()
+        && expected_events
+            .iter()
+            .zip(actual_events.iter())
+            .all(|(e, a)| **e == *a);
+    if !same {
+        let _ = writeln!(diffs, "  bus events (expected / got):");
+        let rows = expected_events.len().max(actual_events.len());
+        for row in 0..rows {
+            let exp = expected_events.get(row);
+            let got = actual_events.get(row);
+            let marker = match (exp, got) {
+                (Some(e), Some(a)) if **e == *a => ' ',
+                _ => '!',
+            };
+            let _ = writeln!(
+                diffs,
+                "  {} {:<20} {}",
+                marker,
+                exp.map_or(String::new(), |e| e.describe()),
+                got.map_or(String::new(), |e| e.describe())
+            );
+        }
+    }
+
+    diffs
+}
+
+#[test]
+#[ignore = "needs FUSE_TESTS_DIR"]
+fn fuse_core_tests() {
+    let dir = env::var_os("FUSE_TESTS_DIR")
+        .map(PathBuf::from)
+        .expect("Set FUSE_TESTS_DIR to the directory with tests.in");
+
+    let inputs = parse_inputs(&fs::read_to_string(dir.join("tests.in")).expect("tests.in"));
+    let expected =
+        parse_expected(&fs::read_to_string(dir.join("tests.expected")).expect("tests.expected"));
+    assert_eq!(
+        inputs.len(),
+        expected.len(),
+        "tests.in and tests.expected differ in length"
+    );
+
+    let mut report = String::new();
+    let mut failures = 0;
+
+    for (input, (name, expected)) in inputs.iter().zip(&expected) {
+        assert_eq!(&input.name, name, "Test files are out of step");
+
+        let diffs = run_test(input, expected);
+        if !diffs.is_empty() {
+            failures += 1;
+            let _ = write!(report, "{}:\n{}", input.name, diffs);
+        }
+    }
+
+    assert!(
+        failures == 0,
+        "{} of {} tests failed:\n{}",
+        failures,
+        inputs.len(),
+        report
+    );
+}
@@ -0,0 +1,216 @@
+// Maskable interrupt response with different bytes on the data bus during
+// the acknowledge cycle, coming out of HALT, and the points at which an
+// interrupt can't be taken
+
+mod common;
+
+use common::{ORIGIN, STACK_TOP, TestBus};
+use zx_spectrum_emulator::cpu::{Cpu, StepKind};
+use zx_spectrum_emulator::emulator::SplitBus;
+use zx_spectrum_emulator::io::{IoController, IoDevice};
+use zx_spectrum_emulator::memory::{FlatRam, MemoryBus};
+
+// Drives a fixed byte onto the bus for each interrupt acknowledge
+struct Vector(u8);
+
+impl IoDevice for Vector {
+    fn read(&mut self, _port: u16) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, _port: u16, _val: u8) {}
+
+    fn interrupt_data(&mut self) -> Option<u8> {
+        Some(self.0)
+    }
+}
+
+// Take one interrupt in the given mode, with data on the bus if Some.
+// Returns the CPU, bus and the T-states taken.
+fn interrupt(
+    mode: u8,
+    data: Option<u8>,
+    setup: impl FnOnce(&mut Cpu, &mut TestBus),
+) -> (Cpu, TestBus, u32) {
+    common::run(
+        &[],
+        |cpu, bus| {
+            if let Some(byte) = data {
+                bus.interrupt_data = byte;
+            }
+            cpu.interrupt_mode = mode;
+            cpu.iff1 = true;
+            cpu.iff2 = true;
+            cpu.int_line = true;
+            setup(cpu, bus);
+        },
+        1,
+    )
+}
+
+#[test]
+fn im2_vector_floats_high_by_default() {
+    let (cpu, _, tstates) = interrupt(2, None, |cpu, bus| {
+        cpu.i = 0x39;
+        bus.write_word(0x39FF, 0x1234);
+    });
+    assert_eq!(cpu.pc, 0x1234);
+    assert_eq!(tstates, 19);
+}
+
+#[test]
+fn im2_vector_from_device() {
+    let (cpu, bus, _) = interrupt(2, Some(0x40), |cpu, bus| {
+        cpu.i = 0xFE;
+        bus.write_word(0xFE40, 0xABCD);
+    });
+    assert_eq!(cpu.pc, 0xABCD);
+    assert!(!cpu.iff1 && !cpu.iff2);
+    assert_eq!(cpu.sp, STACK_TOP - 2);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN);
+}
+
+#[test]
+fn im0_executes_rst_from_bus() {
+    // RST 38h floats onto the bus on a stock machine
+    let (cpu, bus, tstates) = interrupt(0, None, |_, _| {});
+    assert_eq!(cpu.pc, 0x0038);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN);
+    assert_eq!(tstates, 13);
+
+    // RST 10h driven onto the bus
+    let (cpu, bus, tstates) = interrupt(0, Some(0xD7), |_, _| {});
+    assert_eq!(cpu.pc, 0x0010);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN);
+    assert_eq!(tstates, 13);
+}
+
+#[test]
+fn im0_executes_single_byte_instructions() {
+    // NOP: the interrupted program carries on where it was
+    let (cpu, _, tstates) = interrupt(0, Some(0x00), |_, _| {});
+    assert_eq!(cpu.pc, ORIGIN);
+    assert_eq!(tstates, 6);
+
+    // EI re-enables interrupts
+    let (cpu, _, _) = interrupt(0, Some(0xFB), |_, _| {});
+    assert_eq!(cpu.pc, ORIGIN);
+    assert!(cpu.iff1 && cpu.iff2);
+
+    // INC A
+    let (cpu, _, _) = interrupt(0, Some(0x3C), |cpu, _| cpu.a = 0x41);
+    assert_eq!(cpu.a, 0x42);
+    assert_eq!(cpu.pc, ORIGIN);
+}
+
+#[test]
+fn halt_resumes_after_the_interrupt() {
+    // HALT, with RET at the IM 1 handler
+    let (mut cpu, mut bus) = common::setup(&[0x76]);
+    bus.load(0x0038, &[0xC9]);
+    cpu.interrupt_mode = 1;
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+
+    // A halted step and the emulator's halted fast path both run NOPs as
+    // M1 cycles on the bus, and stay on the HALT
+    let mut tstates = cpu.step(&mut bus);
+    tstates += cpu.step(&mut bus);
+    tstates += cpu.run_halted(&mut bus, 100);
+    assert!(cpu.is_halted);
+    assert_eq!(cpu.pc, ORIGIN);
+    assert_eq!(cpu.r, 102);
+    assert_eq!(tstates, 408);
+    assert_eq!(bus.ticks, 408);
+
+    cpu.int_line = true;
+    assert_eq!(cpu.step(&mut bus), 13);
+    assert!(!cpu.is_halted);
+    assert_eq!(cpu.pc, 0x0038);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN + 1);
+
+    cpu.int_line = false;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.pc, ORIGIN + 1);
+}
+
+// A CPU in IM 1 with /INT held and interrupts disabled, about to run
+// program
+fn int_held(program: &[u8]) -> (Cpu, TestBus) {
+    let (mut cpu, bus) = common::setup(program);
+    cpu.interrupt_mode = 1;
+    cpu.int_line = true;
+    (cpu, bus)
+}
+
+#[test]
+fn ei_delays_the_interrupt_by_one_instruction() {
+    // EI; INC A: INC A runs before the interrupt is taken
+    let (mut cpu, mut bus) = int_held(&[0xFB, 0x3C]);
+    cpu.step(&mut bus);
+    assert!(cpu.iff1);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Instruction);
+    assert_eq!(cpu.a, 0x01);
+
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Interrupt);
+    assert_eq!(cpu.pc, 0x0038);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN + 2);
+}
+
+#[test]
+fn ei_after_ei_delays_again() {
+    // EI; EI; EI; NOP: no interrupt until the NOP has run
+    let (mut cpu, mut bus) = int_held(&[0xFB, 0xFB, 0xFB, 0x00]);
+    for _ in 0..4 {
+        cpu.step(&mut bus);
+        assert_eq!(cpu.last_step(), StepKind::Instruction);
+    }
+    assert_eq!(cpu.pc, ORIGIN + 4);
+
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Interrupt);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN + 4);
+}
+
+#[test]
+fn no_interrupt_between_prefixes() {
+    // DD DD LD IX,$1234: /INT rises after the first prefix's step, but the
+    // second prefix and its opcode still run before the interrupt
+    let (mut cpu, mut bus) = int_held(&[0xDD, 0xDD, 0x21, 0x34, 0x12]);
+    cpu.int_line = false;
+    cpu.iff1 = true;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.pc, ORIGIN + 2);
+
+    cpu.int_line = true;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Prefix);
+    assert_eq!(cpu.ix, 0x1234);
+
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Interrupt);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN + 5);
+}
+
+// Devices on the IoController drive the bus together
+#[test]
+fn devices_are_wired_and() {
+    let mut io = IoController::new();
+    io.attach(Box::new(Vector(0xF7)));
+    io.attach(Box::new(Vector(0x7F)));
+
+    let mut memory = FlatRam::new();
+    let mut cpu = Cpu::default();
+    cpu.pc = ORIGIN;
+    cpu.sp = STACK_TOP;
+    cpu.interrupt_mode = 2;
+    cpu.iff1 = true;
+    cpu.int_line = true;
+    cpu.i = 0x80;
+    memory.write_word(0x8077, 0x4321);
+
+    cpu.step(&mut SplitBus::new(&mut memory, &mut io));
+    assert_eq!(cpu.pc, 0x4321);
+}
@@ -0,0 +1,25 @@
+// Port decoding in the IoController
+
+use zx_spectrum_emulator::io::{IoBus, IoController};
+
+#[test]
+fn unused_ports_float_high() {
+    // Odd ports with A5 low are the Kempston's, and read 0xFF without one
+    let mut io = IoController::new();
+    assert!(io.kempston().is_none());
+    assert_eq!(io.port_in(0x001F), 0xFF);
+    assert_eq!(io.port_in(0xFFDF), 0xFF);
+}
+
+#[test]
+fn kempston_decodes_a5_low() {
+    let mut io = IoController::new();
+    io.attach_kempston();
+    assert_eq!(io.port_in(0x001F), 0x00);
+
+    // Up and fire
+    io.kempston_mut().unwrap().set_state(0x18);
+    assert_eq!(io.port_in(0x001F), 0x18);
+    assert_eq!(io.port_in(0x00DF), 0x18);
+    assert_eq!(io.port_in(0x003F), 0xFF);
+}
@@ -0,0 +1,71 @@
+// MEMPTR (WZ), the hidden register instructions leave an address in, and
+// the way BIT n,(HL) leaks it into flags 5 and 3
+
+mod common;
+
+use zx_spectrum_emulator::cpu::Cpu;
+
+fn wz_after(program: &[u8], prepare: impl FnOnce(&mut Cpu)) -> u16 {
+    common::run(program, |cpu, _| prepare(cpu), 1).0.wz
+}
+
+#[test]
+fn loads_through_an_address() {
+    // LD A,($1234): the address plus one
+    assert_eq!(wz_after(&[0x3A, 0x34, 0x12], |_| {}), 0x1235);
+    // LD ($1234),A: A, then the address's low byte plus one
+    assert_eq!(wz_after(&[0x32, 0x34, 0x12], |cpu| cpu.a = 0x56), 0x5635);
+    // LD HL,($1234)
+    assert_eq!(wz_after(&[0x2A, 0x34, 0x12], |_| {}), 0x1235);
+    // LD A,(BC)
+    assert_eq!(wz_after(&[0x0A], |cpu| cpu.set_bc(0x4000)), 0x4001);
+}
+
+#[test]
+fn jumps_and_calls_hold_the_target() {
+    // JP $1234; JP NZ,$1234 not taken; CALL $1234
+    assert_eq!(wz_after(&[0xC3, 0x34, 0x12], |_| {}), 0x1234);
+    assert_eq!(wz_after(&[0xC2, 0x34, 0x12], |cpu| cpu.f = 0x40), 0x1234);
+    assert_eq!(wz_after(&[0xCD, 0x34, 0x12], |_| {}), 0x1234);
+}
+
+#[test]
+fn add_hl_holds_hl_plus_one() {
+    // ADD HL,BC; ADD IX,DE
+    assert_eq!(
+        wz_after(&[0x09], |cpu| {
+            cpu.set_hl(0x1FFF);
+            cpu.set_bc(0x0101);
+        }),
+        0x2000
+    );
+    assert_eq!(wz_after(&[0xDD, 0x19], |cpu| cpu.ix = 0x8000), 0x8001);
+}
+
+#[test]
+fn indexed_addressing_holds_the_address() {
+    // LD A,(IX+$05); LD B,(IY-$02)
+    assert_eq!(wz_after(&[0xDD, 0x7E, 0x05], |cpu| cpu.ix = 0x9000), 0x9005);
+    assert_eq!(wz_after(&[0xFD, 0x46, 0xFE], |cpu| cpu.iy = 0x9000), 0x8FFE);
+}
+
+#[test]
+fn bit_hl_flags_come_from_memptr() {
+    // LD A,($2800) leaves $2801, whose high byte has bits 5 and 3 set;
+    // BIT 0,(HL) then copies them into F whatever (HL) holds
+    let program = [0x3A, 0x00, 0x28, 0xCB, 0x46];
+    let (cpu, _, _) = common::run(&program, |cpu, _| cpu.set_hl(0x9000), 2);
+    assert_eq!(cpu.f & 0x28, 0x28);
+
+    // LD A,($0000) leaves $0001, so both are clear, even with (HL) = $FF
+    let program = [0x3A, 0x00, 0x00, 0xCB, 0x46];
+    let (cpu, _, _) = common::run(
+        &program,
+        |cpu, bus| {
+            cpu.set_hl(0x9000);
+            bus.memory[0x9000] = 0xFF;
+        },
+        2,
+    );
+    assert_eq!(cpu.f & 0x28, 0x00);
+}
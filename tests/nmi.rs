@@ -0,0 +1,73 @@
+// Non-maskable interrupt response and the return from it with RETN
+
+mod common;
+
+use common::{ORIGIN, STACK_TOP};
+use zx_spectrum_emulator::cpu::StepKind;
+
+#[test]
+fn nmi_calls_0066_and_saves_iff1() {
+    // Interrupts enabled, with /INT held so the NMI has to beat it
+    let (mut cpu, mut bus) = common::setup(&[0x00]);
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    cpu.int_line = true;
+    cpu.trigger_nmi();
+
+    assert_eq!(cpu.step(&mut bus), 11);
+    assert_eq!(cpu.last_step(), StepKind::Nmi);
+    assert_eq!(cpu.pc, 0x0066);
+    assert!(!cpu.iff1);
+    assert!(cpu.iff2);
+    assert_eq!(cpu.sp, STACK_TOP - 2);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN);
+
+    // The maskable interrupt waits, as IFF1 is now clear
+    cpu.step(&mut bus);
+    assert_eq!(cpu.last_step(), StepKind::Instruction);
+}
+
+#[test]
+fn retn_restores_iff1() {
+    // RETN at the NMI handler
+    let (mut cpu, mut bus) = common::setup(&[0x00]);
+    bus.load(0x0066, &[0xED, 0x45]);
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    cpu.trigger_nmi();
+
+    cpu.step(&mut bus);
+    assert!(!cpu.iff1);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.pc, ORIGIN);
+    assert_eq!(cpu.sp, STACK_TOP);
+    assert!(cpu.iff1 && cpu.iff2);
+}
+
+#[test]
+fn nmi_with_interrupts_disabled() {
+    // With IFF1 clear RETN leaves interrupts disabled
+    let (mut cpu, mut bus) = common::setup(&[0x00]);
+    bus.load(0x0066, &[0xED, 0x45]);
+    cpu.trigger_nmi();
+
+    cpu.step(&mut bus);
+    assert!(!cpu.iff1 && !cpu.iff2);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.pc, ORIGIN);
+    assert!(!cpu.iff1 && !cpu.iff2);
+}
+
+#[test]
+fn nmi_ends_halt() {
+    // HALT: the NMI returns to the instruction after it
+    let (mut cpu, mut bus) = common::setup(&[0x76]);
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert!(cpu.is_halted);
+
+    cpu.trigger_nmi();
+    assert_eq!(cpu.step(&mut bus), 11);
+    assert!(!cpu.is_halted);
+    assert_eq!(bus.read_word(cpu.sp), ORIGIN + 1);
+}
@@ -0,0 +1,112 @@
+// R counts M1 cycles: one per opcode fetch, so CB, DD, ED and FD prefixed
+// instructions add two (DDCB/FDCB too, as their opcode isn't read in an M1
+// cycle). Only the bottom 7 bits count.
+
+mod common;
+
+fn r_after(program: &[u8], steps: usize) -> u8 {
+    common::run(program, |_, _| {}, steps).0.r
+}
+
+#[test]
+fn unprefixed_instructions_add_one() {
+    // NOP; LD BC,nn; LD A,(nn)
+    assert_eq!(r_after(&[0x00, 0x01, 0x34, 0x12, 0x3A, 0x00, 0x90], 3), 3);
+}
+
+#[test]
+fn prefixed_instructions_add_two() {
+    let cases: [(&str, &[u8]); 6] = [
+        ("RLC B", &[0xCB, 0x00]),
+        ("NEG", &[0xED, 0x44]),
+        ("LDI", &[0xED, 0xA0]),
+        ("LD IX,nn", &[0xDD, 0x21, 0x34, 0x12]),
+        ("LD A,(IY+d)", &[0xFD, 0x7E, 0x05]),
+        // Undefined ED opcode, run as a NOP
+        ("ED 00", &[0xED, 0x00]),
+    ];
+    for (name, program) in cases {
+        assert_eq!(r_after(program, 1), 2, "{}", name);
+    }
+}
+
+#[test]
+fn index_bit_instructions_add_two() {
+    // RLC (IX+5); BIT 0,(IY-2); SET 7,(IX+0),A
+    assert_eq!(r_after(&[0xDD, 0xCB, 0x05, 0x06], 1), 2);
+    assert_eq!(r_after(&[0xFD, 0xCB, 0xFE, 0x46], 1), 2);
+    assert_eq!(r_after(&[0xDD, 0xCB, 0x00, 0xFF], 1), 2);
+}
+
+#[test]
+fn ignored_index_prefixes_still_count() {
+    // DD before an opcode that doesn't use HL
+    assert_eq!(r_after(&[0xDD, 0x00], 1), 2);
+    // DD DD LD IX,nn: the first prefix runs as a NOP step of its own
+    assert_eq!(r_after(&[0xDD, 0xDD, 0x21, 0x34, 0x12], 2), 3);
+    // DD ED NEG
+    assert_eq!(r_after(&[0xDD, 0xED, 0x44], 1), 3);
+}
+
+#[test]
+fn bit_7_is_kept() {
+    let (cpu, _, _) = common::run(&[0x00, 0x00], |cpu, _| cpu.r = 0xFF, 2);
+    assert_eq!(cpu.r, 0x81);
+    let (cpu, _, _) = common::run(&[0x00], |cpu, _| cpu.r = 0x7F, 1);
+    assert_eq!(cpu.r, 0x00);
+}
+
+#[test]
+fn halted_cpu_and_interrupts_refresh() {
+    // HALT, then three halted NOPs
+    let (cpu, _, _) = common::run(&[0x76], |_, _| {}, 4);
+    assert!(cpu.is_halted);
+    assert_eq!(cpu.r, 4);
+
+    // Accepting an IM 1 interrupt is one M1 cycle
+    let (cpu, _, _) = common::run(
+        &[0x00],
+        |cpu, _| {
+            cpu.interrupt_mode = 1;
+            cpu.iff1 = true;
+            cpu.int_line = true;
+        },
+        1,
+    );
+    assert_eq!(cpu.pc, 0x0038);
+    assert_eq!(cpu.r, 1);
+
+    // So is an NMI
+    let (cpu, _, _) = common::run(&[0x00], |cpu, _| cpu.trigger_nmi(), 1);
+    assert_eq!(cpu.pc, 0x0066);
+    assert_eq!(cpu.r, 1);
+}
+
+#[test]
+fn ld_a_r_sees_both_m1_cycles() {
+    // LD R,A then LD A,R: R has moved on by two when it's read
+    let (cpu, _, _) = common::run(&[0xED, 0x4F, 0xED, 0x5F], |cpu, _| cpu.a = 0x40, 2);
+    assert_eq!(cpu.a, 0x42);
+    assert_eq!(cpu.r, 0x42);
+}
+
+#[test]
+fn ld_a_r_flags() {
+    // S, Z, 5 and 3 from the value, H and N reset, P/V from IFF2 and C kept
+    let (cpu, _, _) = common::run(
+        &[0xED, 0x5F],
+        |cpu, _| {
+            cpu.r = 0xA6;
+            cpu.f = 0xFF;
+            cpu.iff2 = true;
+        },
+        1,
+    );
+    assert_eq!(cpu.a, 0xA8);
+    assert_eq!(cpu.f, 0xA8 | 0x04 | 0x01);
+
+    // Bit 7 of R is kept, so only the bottom 7 bits can wrap to zero
+    let (cpu, _, _) = common::run(&[0xED, 0x5F], |cpu, _| cpu.r = 0x7E, 1);
+    assert_eq!(cpu.a, 0x00);
+    assert_eq!(cpu.f, 0x40);
+}
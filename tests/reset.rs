@@ -0,0 +1,115 @@
+// What /RESET and switching on do to the CPU, memory and peripherals
+
+mod common;
+
+use common::TestBus;
+use zx_spectrum_emulator::cpu::{Cpu, CpuVariant};
+use zx_spectrum_emulator::io::{IoBus, IoController};
+use zx_spectrum_emulator::memory::{Memory, MemoryBus};
+
+#[test]
+fn reset_keeps_general_registers() {
+    let mut cpu = Cpu::default();
+    cpu.set_bc(0x1234);
+    cpu.set_hl(0x5678);
+    cpu.a = 0x9A;
+    cpu.ix = 0xBCDE;
+    cpu.sp = 0x8000;
+    cpu.pc = 0x4321;
+    cpu.i = 0x3F;
+    cpu.r = 0x55;
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    cpu.interrupt_mode = 2;
+    cpu.is_halted = true;
+    cpu.nmi_pending = true;
+
+    cpu.reset();
+    assert_eq!((cpu.pc, cpu.i, cpu.r, cpu.interrupt_mode), (0, 0, 0, 0));
+    assert!(!cpu.iff1 && !cpu.iff2);
+    assert!(!cpu.is_halted && !cpu.nmi_pending);
+    assert_eq!((cpu.bc(), cpu.hl(), cpu.a), (0x1234, 0x5678, 0x9A));
+    assert_eq!((cpu.ix, cpu.sp), (0xBCDE, 0x8000));
+}
+
+#[test]
+fn reset_takes_effect_before_the_next_instruction() {
+    let mut bus = TestBus::new();
+    let mut cpu = Cpu::default();
+    // LD A,$42 at 0, with a pending NMI that the reset clears
+    bus.load(0, &[0x3E, 0x42]);
+    cpu.pc = 0x9000;
+    cpu.trigger_nmi();
+
+    cpu.reset();
+    cpu.step(&mut bus);
+    assert_eq!(cpu.a, 0x42);
+    assert_eq!(cpu.pc, 0x0002);
+}
+
+#[test]
+fn power_on_fills_registers() {
+    let mut cpu = Cpu::new(CpuVariant::Cmos);
+    cpu.power_on(|| 0xFF);
+    assert_eq!(
+        (cpu.af(), cpu.bc(), cpu.de(), cpu.hl()),
+        (0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF)
+    );
+    assert_eq!(
+        (cpu.ix, cpu.iy, cpu.sp, cpu.af_shadow),
+        (0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF)
+    );
+    assert_eq!((cpu.pc, cpu.i, cpu.r), (0, 0, 0));
+    assert_eq!(cpu.variant, CpuVariant::Cmos);
+
+    // AF and SP are 0xFFFF whatever else the registers settle to
+    let mut next = 0u8;
+    cpu.power_on(|| {
+        next = next.wrapping_add(1);
+        next
+    });
+    assert_eq!((cpu.af(), cpu.sp), (0xFFFF, 0xFFFF));
+    assert_eq!(cpu.bc(), 0x0201);
+    assert_eq!(cpu.de(), 0x0403);
+    assert_eq!(cpu.hl(), 0x0605);
+    assert!(!cpu.iff1 && !cpu.iff2);
+}
+
+#[test]
+fn power_on_fills_ram_and_keeps_rom() {
+    let rom: Vec<u8> = (0..0x4000).map(|addr| addr as u8).collect();
+    let mut memory = Memory::new(rom.clone());
+    let mut next = 0u8;
+    memory.power_on(|| {
+        next = next.wrapping_add(3);
+        next
+    });
+
+    assert_eq!(memory.rom(), rom.as_slice());
+    assert_eq!(memory.read(0x3FFF), 0xFF);
+    assert_eq!(memory.read(0x4000), 3);
+    assert_eq!(memory.read(0x4001), 6);
+
+    memory.power_on(|| 0xFF);
+    assert!((0x4000..=0xFFFF).all(|addr| memory.read(addr) == 0xFF));
+    assert_eq!(memory.rom(), rom.as_slice());
+}
+
+#[test]
+fn io_reset_clears_ula_and_joystick() {
+    let mut io = IoController::new();
+    io.attach_kempston();
+    io.port_out(0x00FE, 0x1A);
+    io.ula_mut().set_key(0, 1, true);
+    io.kempston_mut().unwrap().set_state(0x10);
+    assert_eq!(io.port_in(0xFEFE), 0xE0 | 0x1D);
+    assert_eq!(io.port_in(0x001F), 0x10);
+
+    io.reset();
+    let ula = io.ula();
+    assert_eq!(ula.border_colour(), 7);
+    assert!(!ula.ear() && !ula.mic());
+    assert_eq!(io.port_in(0xFEFE), 0xBF);
+    assert_eq!(io.kempston().unwrap().read(), 0);
+    assert_eq!(io.port_in(0x001F), 0x00);
+}
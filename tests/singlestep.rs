@@ -0,0 +1,463 @@
+// Per-instruction conformance against the SingleStepTests Z80 vectors
+// (https://github.com/SingleStepTests/z80). Each JSON file holds 1000 cases
+// for one opcode: the registers and RAM before, the registers and RAM after,
+// the port traffic and one entry per T-state.
+//
+// The vectors are large and live outside the crate. Point SINGLESTEP_DIR at
+// the directory holding the .json files (v1/ in the upstream repo):
+//
+//     SINGLESTEP_DIR=/path/to/z80/v1 cargo test --release --test singlestep -- --ignored
+//
+// Set SINGLESTEP_FILTER to a file name prefix (e.g. "dd cb") to run a subset.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use zx_spectrum_emulator::cpu::{Bus, Cpu, Cycle};
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    // One entry per T-state: the address bus, the data bus if anything
+    // drives it, and the RD, WR, MREQ and IORQ pins as e.g. "r-m-"
+    cycles: Vec<(u16, Option<u8>, String)>,
+    #[serde(default)]
+    ports: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    i: u8,
+    r: u8,
+    ei: u8,
+    wz: u16,
+    ix: u16,
+    iy: u16,
+    af_: u16,
+    bc_: u16,
+    de_: u16,
+    hl_: u16,
+    im: u8,
+    q: u8,
+    iff1: u8,
+    iff2: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+// A memory or port access, with the T-state it happened on
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Access {
+    write: bool,
+    port: bool,
+    addr: u16,
+    data: u8,
+    tstate: u32,
+}
+
+impl Access {
+    fn describe(&self) -> String {
+        let kind = match (self.port, self.write) {
+            (false, false) => "MR",
+            (false, true) => "MW",
+            (true, false) => "PR",
+            (true, true) => "PW",
+        };
+        format!(
+            "{} {:04X}={:02X} at T{}",
+            kind, self.addr, self.data, self.tstate
+        )
+    }
+}
+
+// 64K of RAM and the case's port traffic. Port reads are played back in the
+// order the case expects, noting any made to a different port, and every
+// access is logged with the machine cycle it fell in.
+struct TestBus {
+    memory: Vec<u8>,
+    reads: VecDeque<(u16, u8)>,
+    writes: Vec<(u16, u8)>,
+    wrong_ports: Vec<(u16, u16)>,
+    // The T-state each machine cycle started on, with its kind
+    cycles: Vec<(u32, Cycle)>,
+    tstates: u32,
+    accesses: Vec<Access>,
+}
+
+impl TestBus {
+    // Log an access made in the current machine cycle. The cycle's first
+    // T-state stands in for when it happened until compare() matches it up.
+    fn log(&mut self, addr: u16, data: u8) {
+        let (tstate, cycle) = *self.cycles.last().expect("Access outside a machine cycle");
+        let (write, port) = match cycle {
+            Cycle::OpcodeFetch | Cycle::MemoryRead => (false, false),
+            Cycle::MemoryWrite => (true, false),
+            Cycle::PortRead => (false, true),
+            Cycle::PortWrite => (true, true),
+            other => panic!("{:?} cycle accessed {:04X}", other, addr),
+        };
+        self.accesses.push(Access {
+            write,
+            port,
+            addr,
+            data,
+            tstate,
+        });
+    }
+
+    // The T-states the machine cycle starting at tstate covers
+    fn cycle_span(&self, tstate: u32) -> std::ops::Range<u32> {
+        let end = self
+            .cycles
+            .iter()
+            .map(|&(start, _)| start)
+            .find(|&start| start > tstate)
+            .unwrap_or(self.tstates);
+        tstate..end
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.memory[addr as usize];
+        self.log(addr, val);
+        val
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.log(addr, val);
+        self.memory[addr as usize] = val;
+    }
+
+    fn port_in(&mut self, port: u16) -> u8 {
+        let val = match self.reads.pop_front() {
+            Some((expected, val)) => {
+                if port != expected {
+                    self.wrong_ports.push((expected, port));
+                }
+                val
+            }
+            None => panic!("Unexpected read from port {:04X}", port),
+        };
+        self.log(port, val);
+        val
+    }
+
+    fn port_out(&mut self, port: u16, val: u8) {
+        self.log(port, val);
+        self.writes.push((port, val));
+    }
+
+    fn start_cycle(&mut self, cycle: Cycle, _addr: u16) -> u32 {
+        self.cycles.push((self.tstates, cycle));
+        0
+    }
+
+    fn tick(&mut self, tstates: u32) {
+        self.tstates += tstates;
+    }
+}
+
+// The accesses a case's cycle list shows: runs of T-states with RD or WR
+// active and data on the bus, each timed by its first T-state
+fn expected_accesses(case: &TestCase) -> Vec<Access> {
+    let mut accesses: Vec<Access> = Vec::new();
+    let mut last = None;
+    for (tstate, (addr, data, pins)) in case.cycles.iter().enumerate() {
+        let tstate = tstate as u32;
+        let write = pins.contains('w');
+        let Some(data) = data.filter(|_| write || pins.contains('r')) else {
+            last = None;
+            continue;
+        };
+        let access = Access {
+            write,
+            port: pins.contains('i'),
+            addr: *addr,
+            data,
+            tstate,
+        };
+        // The same access held over from the T-state before
+        let held = last == Some(tstate.wrapping_sub(1))
+            && accesses
+                .last()
+                .is_some_and(|prev| Access { tstate, ..*prev } == access);
+        if !held {
+            accesses.push(access);
+        }
+        last = Some(tstate);
+    }
+    accesses
+}
+
+fn load_state(cpu: &mut Cpu, bus: &mut TestBus, state: &State) {
+    cpu.pc = state.pc;
+    cpu.sp = state.sp;
+    cpu.a = state.a;
+    cpu.f = state.f;
+    cpu.b = state.b;
+    cpu.c = state.c;
+    cpu.d = state.d;
+    cpu.e = state.e;
+    cpu.h = state.h;
+    cpu.l = state.l;
+    cpu.i = state.i;
+    cpu.r = state.r;
+    cpu.int_blocked = state.ei != 0;
+    cpu.wz = state.wz;
+    cpu.ix = state.ix;
+    cpu.iy = state.iy;
+    cpu.af_shadow = state.af_;
+    cpu.bc_shadow = state.bc_;
+    cpu.de_shadow = state.de_;
+    cpu.hl_shadow = state.hl_;
+    cpu.interrupt_mode = state.im;
+    cpu.q = state.q;
+    cpu.iff1 = state.iff1 != 0;
+    cpu.iff2 = state.iff2 != 0;
+
+    for &(addr, val) in &state.ram {
+        bus.memory[addr as usize] = val;
+    }
+}
+
+// Flags as e.g. "SZ-H-PNC", with '-' for clear bits and 5/3 for the
+// undocumented bits
+fn flags(f: u8) -> String {
+    "SZ5H3PNC"
+        .chars()
+        .enumerate()
+        .map(|(i, name)| if f & (0x80 >> i) != 0 { name } else { '-' })
+        .collect()
+}
+
+// Every difference between the CPU and the expected state, one per line
+fn compare(cpu: &Cpu, bus: &TestBus, case: &TestCase) -> String {
+    let exp = &case.expected;
+    let mut diffs = String::new();
+
+    let regs: [(&str, u16, u16); 21] = [
+        ("pc", exp.pc, cpu.pc),
+        ("sp", exp.sp, cpu.sp),
+        ("a", exp.a as u16, cpu.a as u16),
+        ("b", exp.b as u16, cpu.b as u16),
+        ("c", exp.c as u16, cpu.c as u16),
+        ("d", exp.d as u16, cpu.d as u16),
+        ("e", exp.e as u16, cpu.e as u16),
+        ("h", exp.h as u16, cpu.h as u16),
+        ("l", exp.l as u16, cpu.l as u16),
+        ("i", exp.i as u16, cpu.i as u16),
+        ("r", exp.r as u16, cpu.r as u16),
+        ("wz", exp.wz, cpu.wz),
+        ("ix", exp.ix, cpu.ix),
+        ("iy", exp.iy, cpu.iy),
+        ("af'", exp.af_, cpu.af_shadow),
+        ("bc'", exp.bc_, cpu.bc_shadow),
+        ("de'", exp.de_, cpu.de_shadow),
+        ("hl'", exp.hl_, cpu.hl_shadow),
+        ("im", exp.im as u16, cpu.interrupt_mode as u16),
+        ("iff1", exp.iff1 as u16, cpu.iff1 as u16),
+        ("iff2", exp.iff2 as u16, cpu.iff2 as u16),
+    ];
+    for (name, expected, actual) in regs {
+        if expected != actual {
+            let _ = writeln!(
+                diffs,
+                "  {}: expected {:04X}, got {:04X}",
+                name, expected, actual
+            );
+        }
+    }
+
+    if exp.f != cpu.f {
+        let _ = writeln!(
+            diffs,
+            "  f: expected {} ({:02X}), got {} ({:02X})",
+            flags(exp.f),
+            exp.f,
+            flags(cpu.f),
+            cpu.f
+        );
+    }
+    if exp.q != cpu.q {
+        let _ = writeln!(diffs, "  q: expected {:02X}, got {:02X}", exp.q, cpu.q);
+    }
+
+    for &(addr, expected) in &exp.ram {
+        let actual = bus.memory[addr as usize];
+        if expected != actual {
+            let _ = writeln!(
+                diffs,
+                "  ({:04X}): expected {:02X}, got {:02X}",
+                addr, expected, actual
+            );
+        }
+    }
+
+    let expected_writes: Vec<(u16, u8)> = case
+        .ports
+        .iter()
+        .filter(|(_, _, dir)| dir == "w")
+        .map(|&(port, val, _)| (port, val))
+        .collect();
+    if expected_writes != bus.writes {
+        let _ = writeln!(
+            diffs,
+            "  port writes: expected {:02X?}, got {:02X?}",
+            expected_writes, bus.writes
+        );
+    }
+
+    for &(expected, actual) in &bus.wrong_ports {
+        let _ = writeln!(
+            diffs,
+            "  port read: expected {:04X}, got {:04X}",
+            expected, actual
+        );
+    }
+    if !bus.reads.is_empty() {
+        let _ = writeln!(diffs, "  port reads not made: {:04X?}", bus.reads);
+    }
+
+    if bus.tstates as usize != case.cycles.len() {
+        let _ = writeln!(
+            diffs,
+            "  T-states: expected {}, got {}",
+            case.cycles.len(),
+            bus.tstates
+        );
+    }
+
+    // Each access must come in the same order, and fall in a machine cycle
+    // that covers the T-state the case shows it on
+    let expected = expected_accesses(case);
+    for (i, exp) in expected.iter().enumerate() {
+        let matched = bus.accesses.get(i).is_some_and(|actual| {
+            Access {
+                tstate: exp.tstate,
+                ..*actual
+            } == *exp
+                && bus.cycle_span(actual.tstate).contains(&exp.tstate)
+        });
+        if !matched {
+            let actual = bus
+                .accesses
+                .get(i)
+                .map_or("nothing".to_string(), |a| a.describe());
+            let _ = writeln!(
+                diffs,
+                "  access {}: expected {}, got {}",
+                i,
+                exp.describe(),
+                actual
+            );
+        }
+    }
+    for extra in bus.accesses.iter().skip(expected.len()) {
+        let _ = writeln!(diffs, "  unexpected access: {}", extra.describe());
+    }
+
+    diffs
+}
+
+fn run_case(case: &TestCase) -> String {
+    let mut cpu = Cpu::default();
+    let mut bus = TestBus {
+        memory: vec![0; 0x10000],
+        reads: case
+            .ports
+            .iter()
+            .filter(|(_, _, dir)| dir == "r")
+            .map(|&(port, val, _)| (port, val))
+            .collect(),
+        writes: Vec::new(),
+        wrong_ports: Vec::new(),
+        cycles: Vec::new(),
+        tstates: 0,
+        accesses: Vec::new(),
+    };
+    load_state(&mut cpu, &mut bus, &case.initial);
+
+    // A DD/FD prefix followed by another runs as its own step, so keep
+    // going until the case's T-states are used up
+    for _ in 0..4 {
+        cpu.step(&mut bus);
+        if bus.tstates as usize >= case.cycles.len() {
+            break;
+        }
+    }
+
+    compare(&cpu, &bus, case)
+}
+
+#[test]
+#[ignore = "needs SINGLESTEP_DIR"]
+fn singlestep_vectors() {
+    let dir = env::var_os("SINGLESTEP_DIR")
+        .map(PathBuf::from)
+        .expect("Set SINGLESTEP_DIR to the vector directory");
+    let filter = env::var("SINGLESTEP_FILTER").unwrap_or_default();
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("Failed to read SINGLESTEP_DIR")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&filter))
+        })
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "No .json vectors in {}", dir.display());
+
+    // One line per failing opcode, with the first failing case's details
+    let mut report = String::new();
+    let mut failed_opcodes = 0;
+
+    for path in &files {
+        let json = fs::read_to_string(path).expect("Failed to read vector file");
+        let cases: Vec<TestCase> = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+
+        let mut failures = 0;
+        let mut first_failure = None;
+        for case in &cases {
+            let diffs = run_case(case);
+            if !diffs.is_empty() {
+                failures += 1;
+                first_failure.get_or_insert_with(|| format!("  {}\n{}", case.name, diffs));
+            }
+        }
+
+        if let Some(details) = first_failure {
+            failed_opcodes += 1;
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let _ = writeln!(report, "{}: {}/{} failed", name, failures, cases.len());
+            report.push_str(&details);
+        }
+    }
+
+    assert!(
+        failed_opcodes == 0,
+        "{} of {} opcodes failed:\n{}",
+        failed_opcodes,
+        files.len(),
+        report
+    );
+}
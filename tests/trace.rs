@@ -0,0 +1,215 @@
+// The instruction trace: what gets logged for each step, range filtering, the
+// ring buffer and breakpoints
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use zx_spectrum_emulator::cpu::{Cpu, StepKind};
+use zx_spectrum_emulator::emulator::SplitBus;
+use zx_spectrum_emulator::io::IoController;
+use zx_spectrum_emulator::memory::{Memory, MemoryBus};
+use zx_spectrum_emulator::trace::{Tracer, next_instruction};
+
+// LD A,$01; LD B,$02; LD C,$03; LD D,$04; LD E,$05
+const PROGRAM: [u8; 10] = [0x3E, 0x01, 0x06, 0x02, 0x0E, 0x03, 0x16, 0x04, 0x1E, 0x05];
+
+// A writer the test can read back after handing it to the tracer
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Machine {
+    cpu: Cpu,
+    memory: Memory,
+    io: IoController,
+    tstates: u64,
+}
+
+impl Machine {
+    // program in ROM at 0
+    fn new(program: &[u8]) -> Self {
+        let mut rom = vec![0; 0x4000];
+        rom[..program.len()].copy_from_slice(program);
+        Self {
+            cpu: Cpu::default(),
+            memory: Memory::new(rom),
+            io: IoController::new(),
+            tstates: 0,
+        }
+    }
+
+    // Run steps, tracing each one as the emulator does
+    fn run(&mut self, tracer: &mut Tracer, steps: usize) {
+        for _ in 0..steps {
+            let before = self.cpu.clone();
+            let instruction = next_instruction(&self.cpu, &self.memory);
+            let tstates = self
+                .cpu
+                .step(&mut SplitBus::new(&mut self.memory, &mut self.io));
+            tracer
+                .trace(&before, self.cpu.last_step(), &instruction, self.tstates)
+                .unwrap();
+            self.tstates += tstates as u64;
+        }
+    }
+}
+
+fn tracer() -> (Tracer, Output) {
+    let output = Output::default();
+    (Tracer::new(Box::new(output.clone())), output)
+}
+
+#[test]
+fn lines_show_the_state_before_each_instruction() {
+    let (mut tracer, output) = tracer();
+    let mut machine = Machine::new(&PROGRAM);
+    machine.run(&mut tracer, 2);
+
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("T=0000000000 PC=0000 LD A,$01 "));
+    assert!(lines[0].contains("AF=0000 "));
+    assert!(lines[1].starts_with("T=0000000007 PC=0002 LD B,$02 "));
+    assert!(lines[1].contains("AF=0100 "));
+}
+
+#[test]
+fn ring_buffer_keeps_the_last_lines() {
+    let (mut tracer, output) = tracer();
+    tracer.set_ring_buffer(3);
+    let mut machine = Machine::new(&PROGRAM);
+    machine.run(&mut tracer, 5);
+    assert!(output.lines().is_empty());
+
+    tracer.dump("done").unwrap();
+    let lines = output.lines();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains("PC=0004 LD C,$03"));
+    assert!(lines[1].contains("PC=0006 LD D,$04"));
+    assert!(lines[2].contains("PC=0008 LD E,$05"));
+    assert_eq!(lines[3], "--- done ---");
+
+    // The dump empties the buffer
+    tracer.dump("again").unwrap();
+    assert_eq!(output.lines().len(), 5);
+}
+
+#[test]
+fn ranges_filter_by_address() {
+    let (mut tracer, output) = tracer();
+    tracer.add_range(0x0002..=0x0003);
+    tracer.add_range(0x0008..=0x0008);
+    let mut machine = Machine::new(&PROGRAM);
+    machine.run(&mut tracer, 5);
+
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("PC=0002 LD B,$02"));
+    assert!(lines[1].contains("PC=0008 LD E,$05"));
+}
+
+#[test]
+fn breakpoint_dumps_the_ring_buffer() {
+    let (mut tracer, output) = tracer();
+    tracer.set_ring_buffer(10);
+    tracer.add_breakpoint(0x0004);
+    let mut machine = Machine::new(&PROGRAM);
+
+    machine.run(&mut tracer, 2);
+    assert!(output.lines().is_empty());
+
+    // The breakpoint's own line comes before the marker, and later steps
+    // go back into the buffer
+    machine.run(&mut tracer, 3);
+    let lines = output.lines();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[2].contains("PC=0004 LD C,$03"));
+    assert_eq!(lines[3], "--- breakpoint at $0004 ---");
+}
+
+#[test]
+fn interrupts_get_their_own_line() {
+    let (mut tracer, output) = tracer();
+    tracer.add_breakpoint(0x0000);
+    let mut machine = Machine::new(&PROGRAM);
+    machine.cpu.interrupt_mode = 1;
+    machine.cpu.iff1 = true;
+    machine.cpu.int_line = true;
+
+    // Accepting the interrupt doesn't run the instruction at the breakpoint
+    machine.run(&mut tracer, 1);
+    assert_eq!(machine.cpu.last_step(), StepKind::Interrupt);
+    machine.cpu.int_line = false;
+    machine.cpu.trigger_nmi();
+    machine.run(&mut tracer, 1);
+    assert_eq!(machine.cpu.last_step(), StepKind::Nmi);
+
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("T=0000000000 PC=0000 INT (IM 1) "));
+    assert!(lines[1].starts_with("T=0000000013 PC=0038 NMI "));
+}
+
+#[test]
+fn prefix_steps_show_the_instruction_that_ran() {
+    // DD DD LD IX,$1234: the first prefix is a step of its own
+    let (mut tracer, output) = tracer();
+    let mut machine = Machine::new(&[0xDD, 0xDD, 0x21, 0x34, 0x12]);
+    machine.run(&mut tracer, 2);
+    assert_eq!(machine.cpu.last_step(), StepKind::Prefix);
+
+    let lines = output.lines();
+    assert!(lines[0].contains("PC=0000 NOP "));
+    assert!(lines[1].contains("PC=0001 LD IX,$1234 "));
+}
+
+#[test]
+fn index_prefix_before_ed_runs_with_it() {
+    // LD A,$05; DD NEG: the CPU runs DD ED 44 as one step
+    let (mut tracer, output) = tracer();
+    let mut machine = Machine::new(&[0x3E, 0x05, 0xDD, 0xED, 0x44]);
+    machine.run(&mut tracer, 2);
+    assert_eq!(machine.cpu.pc, 0x0005);
+    assert_eq!(machine.cpu.a, 0xFB);
+
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("T=0000000007 PC=0002 NEG "));
+}
+
+#[test]
+fn instructions_are_shown_as_they_were_before_running() {
+    // LD ($8001),A at $8000 overwrites its own address's low byte
+    let (mut tracer, output) = tracer();
+    let mut machine = Machine::new(&[]);
+    for (addr, byte) in (0x8000..).zip([0x32, 0x01, 0x80]) {
+        machine.memory.write(addr, byte);
+    }
+    machine.cpu.pc = 0x8000;
+    machine.run(&mut tracer, 1);
+    assert_eq!(machine.memory.read(0x8001), 0x00);
+
+    let lines = output.lines();
+    assert!(lines[0].contains("PC=8000 LD ($8001),A "));
+}
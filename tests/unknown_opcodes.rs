@@ -0,0 +1,58 @@
+// Unknown opcodes are noted by the step that runs them, and the emulator's
+// policy decides whether to stop for them
+
+mod common;
+
+use zx_spectrum_emulator::cpu::UnknownOpcode;
+use zx_spectrum_emulator::emulator::{EmulatorEvent, UnknownOpcodePolicy};
+
+#[test]
+fn policy_decides_the_event() {
+    // DD 00: the prefix does nothing to NOP
+    let (cpu, _, _) = common::run(&[0xDD, 0x00], |_, _| {}, 1);
+    let op = cpu.unknown_opcode().unwrap();
+    assert_eq!(
+        op,
+        UnknownOpcode {
+            addr: common::ORIGIN,
+            prefix: 0xDD,
+            opcode: 0x00,
+        }
+    );
+
+    assert_eq!(UnknownOpcodePolicy::Nop.event(op), None);
+    assert_eq!(
+        UnknownOpcodePolicy::Stop.event(op),
+        Some(EmulatorEvent::UnknownOpcode(op))
+    );
+    assert_eq!(
+        UnknownOpcodePolicy::Break.event(op),
+        Some(EmulatorEvent::Break(op))
+    );
+}
+
+#[test]
+fn halting_on_a_prefixed_halt_reports_it_once() {
+    // DD 76 and FD 76 halt as HALT does. Neither a halted step nor the
+    // emulator's halted fast path runs it again, so no policy stops for it
+    // after the step that did.
+    for prefix in [0xDD, 0xFD] {
+        let (mut cpu, mut bus) = common::setup(&[prefix, 0x76]);
+        cpu.step(&mut bus);
+        assert!(cpu.is_halted);
+        assert_eq!(cpu.unknown_opcode().map(|op| op.opcode), Some(0x76));
+
+        let mut halted = cpu.clone();
+        halted.step(&mut bus);
+        assert_eq!(halted.unknown_opcode(), None);
+
+        cpu.run_halted(&mut bus, 100);
+        for policy in [
+            UnknownOpcodePolicy::Nop,
+            UnknownOpcodePolicy::Stop,
+            UnknownOpcodePolicy::Break,
+        ] {
+            assert_eq!(cpu.unknown_opcode().and_then(|op| policy.event(op)), None);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Undocumented behaviour that differs between Z80 chip families
+
+mod common;
+
+use zx_spectrum_emulator::cpu::CpuVariant;
+
+const VARIANTS: [CpuVariant; 3] = [CpuVariant::ZilogNmos, CpuVariant::NecNmos, CpuVariant::Cmos];
+
+#[test]
+fn out_c_0() {
+    for variant in VARIANTS {
+        let (_, bus, _) = common::run(
+            &[0xED, 0x71],
+            |cpu, _| {
+                cpu.variant = variant;
+                cpu.set_bc(0x12FE);
+            },
+            1,
+        );
+        let expected = if variant == CpuVariant::Cmos {
+            0xFF
+        } else {
+            0x00
+        };
+        assert_eq!(bus.writes, [(0x12FE, expected)], "{:?}", variant);
+    }
+}
+
+#[test]
+fn scf_flags_3_and_5() {
+    // Bits 3 and 5 of A always reach the flags
+    for variant in VARIANTS {
+        let (cpu, _, _) = common::run(
+            &[0x37],
+            |cpu, _| {
+                cpu.variant = variant;
+                cpu.a = 0x28;
+            },
+            1,
+        );
+        assert_eq!(cpu.f & 0x28, 0x28, "{:?}", variant);
+    }
+
+    // After an instruction that leaves the flags alone Q is 0, so bits
+    // already set in F leak through on Zilog NMOS, and flag 5 on CMOS
+    let expected = [
+        (CpuVariant::ZilogNmos, 0x28),
+        (CpuVariant::NecNmos, 0x00),
+        (CpuVariant::Cmos, 0x20),
+    ];
+    for (variant, xy) in expected {
+        let (cpu, _, _) = common::run(
+            &[0x00, 0x37],
+            |cpu, _| {
+                cpu.variant = variant;
+                cpu.f = 0x28;
+            },
+            2,
+        );
+        assert_eq!(cpu.f & 0x28, xy, "{:?}", variant);
+    }
+}
+
+#[test]
+fn ld_a_i_interrupted() {
+    for variant in VARIANTS {
+        for interrupted in [false, true] {
+            // LD A,I with interrupts enabled, then /INT goes active
+            let (mut cpu, mut bus, _) = common::run(
+                &[0xED, 0x57, 0x00],
+                |cpu, _| {
+                    cpu.variant = variant;
+                    cpu.interrupt_mode = 1;
+                    cpu.iff1 = true;
+                    cpu.iff2 = true;
+                },
+                1,
+            );
+            assert_eq!(cpu.f & 0x04, 0x04, "{:?}", variant);
+
+            cpu.int_line = interrupted;
+            cpu.step(&mut bus);
+            let expected = if interrupted && variant != CpuVariant::Cmos {
+                0x00
+            } else {
+                0x04
+            };
+            assert_eq!(cpu.f & 0x04, expected, "{:?}", variant);
+        }
+    }
+}
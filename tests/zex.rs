@@ -0,0 +1,105 @@
+// Runs Frank Cringle's ZEXDOC/ZEXALL instruction exercisers on the CPU.
+//
+// The .COM images are GPL so they aren't shipped with the crate. Point
+// ZEX_DIR at a directory holding zexdoc.com and zexall.com (any case) and
+// run in release mode, as each suite executes billions of instructions:
+//
+//     ZEX_DIR=/path/to/zex cargo test --release --test zex -- --ignored
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use zx_spectrum_emulator::cpu::Cpu;
+use zx_spectrum_emulator::emulator::SplitBus;
+use zx_spectrum_emulator::io::IoController;
+use zx_spectrum_emulator::memory::{FlatRam, MemoryBus};
+
+const TPA: u16 = 0x0100; // CP/M loads programs here
+const BDOS: u16 = 0x0005;
+const STACK_TOP: u16 = 0xFF00;
+
+fn find_image(name: &str) -> Option<Vec<u8>> {
+    let dir = PathBuf::from(env::var_os("ZEX_DIR")?);
+    [name.to_lowercase(), name.to_uppercase()]
+        .iter()
+        .find_map(|file| fs::read(dir.join(file)).ok())
+}
+
+// Run a CP/M program until it warm boots (jumps to 0x0000) and return
+// everything it printed through BDOS
+fn run_cpm(image: &[u8]) -> String {
+    let mut memory = FlatRam::new();
+    let mut io = IoController::new();
+    let mut cpu = Cpu::default();
+    let mut output = String::new();
+
+    memory.load(TPA, image);
+    // BDOS entry is a bare RET; the word after it is the top of the TPA,
+    // which the exercisers load into SP
+    memory.write(BDOS, 0xC9);
+    memory.write_word(BDOS + 1, STACK_TOP);
+    cpu.pc = TPA;
+    cpu.sp = STACK_TOP;
+
+    loop {
+        match cpu.pc {
+            0x0000 => break,
+            BDOS => match cpu.c {
+                // C_WRITE: character in E
+                2 => output.push(cpu.e as char),
+                // C_WRITESTR: '$'-terminated string at DE
+                9 => {
+                    let mut addr = cpu.de();
+                    loop {
+                        let ch = memory.read(addr);
+                        if ch == b'$' {
+                            break;
+                        }
+                        output.push(ch as char);
+                        addr = addr.wrapping_add(1);
+                    }
+                }
+                func => panic!("Unsupported BDOS function {}", func),
+            },
+            _ => {}
+        }
+        cpu.step(&mut SplitBus::new(&mut memory, &mut io));
+    }
+
+    output
+}
+
+fn run_exerciser(name: &str) {
+    let image = find_image(name)
+        .unwrap_or_else(|| panic!("Set ZEX_DIR to a directory containing {}", name));
+
+    let output = run_cpm(&image);
+    print!("{}", output);
+
+    let failed: Vec<&str> = output.lines().filter(|l| l.contains("ERROR")).collect();
+    assert!(
+        failed.is_empty(),
+        "{} failures:\n{}",
+        name,
+        failed.join("\n")
+    );
+    assert!(
+        output.contains("Tests complete"),
+        "{} did not finish:\n{}",
+        name,
+        output
+    );
+}
+
+#[test]
+#[ignore = "slow; needs ZEX_DIR and --release"]
+fn zexdoc() {
+    run_exerciser("zexdoc.com");
+}
+
+#[test]
+#[ignore = "slow; needs ZEX_DIR and --release"]
+fn zexall() {
+    run_exerciser("zexall.com");
+}